@@ -2,19 +2,35 @@
 
 
 use anyhow::*;
+use image::GenericImageView;
 use glob::glob;
-use std::fs::{read_to_string,write};
-use std::path::PathBuf;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{read, read_to_string, write};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 
 struct ShaderData {
     src:String,
     src_path:PathBuf,
-    spv_path:PathBuf,
     kind:shaderc::ShaderKind,
+    // from `#pragma permutation FOO BAR`, every combination of these gets defined and compiled
+    flags:Vec<String>,
+    // from `#pragma vertex_buffer instance 5 6 7`, the buffer name and what's in it, in order
+    vertex_buffers:Vec<(String, Vec<BufferEntry>)>,
+    // from `#pragma unfilterable t_source s_source`, textures that can't be filtered and the samplers that read them
+    unfilterable:Vec<String>,
 
 
 }
 
+// one word of a `#pragma vertex_buffer` line
+enum BufferEntry {
+    // an input location the shader reads
+    Location(u32),
+    // `normal:vec3`, something in the buffer no shader reads (yet), it still gets a field and takes up room
+    Unread { name: String, rust_type: String, size: u32 },
+}
 
 impl ShaderData {
     pub fn load(src_path:PathBuf) -> Result<Self> {
@@ -30,24 +46,1132 @@ impl ShaderData {
             _ => bail!("Unsupported shader: {}",src_path.display())
         };
         let src = read_to_string(src_path.clone())?;
-        let spv_path = src_path.with_extension(format!("{}.spv",extension));
+        let flags: Vec<String> = src
+            .lines()
+            .filter_map(|line| pragma(line, "permutation"))
+            .flat_map(|flags| flags.split_whitespace())
+            .map(String::from)
+            .collect();
+        if flags.len() > MAX_PERMUTATION_FLAGS {
+            bail!("{} has {} permutation flags, more than {} makes too many shaders", src_path.display(), flags.len(), MAX_PERMUTATION_FLAGS);
+        }
+        // flags end up as #defines and as field names in shaders.rs, so they have to work as both
+        let mut seen = BTreeSet::new();
+        for flag in &flags {
+            if !is_identifier(flag) || RUST_KEYWORDS.contains(&flag.to_lowercase().as_str()) {
+                bail!("{}: permutation flag {} has to be a plain identifier that isn't a rust keyword", src_path.display(), flag);
+            }
+            if !seen.insert(flag.to_lowercase()) {
+                bail!("{}: permutation flag {} is listed twice", src_path.display(), flag);
+            }
+        }
+        let unfilterable = src
+            .lines()
+            .filter_map(|line| pragma(line, "unfilterable"))
+            .flat_map(|names| names.split_whitespace())
+            .map(String::from)
+            .collect();
+        let mut vertex_buffers = Vec::new();
+        for line in src.lines() {
+            if let Some(buffer) = pragma(line, "vertex_buffer") {
+                let mut words = buffer.split_whitespace();
+                let name = words.next().with_context(|| format!("{}: a vertex_buffer pragma needs a name", src_path.display()))?;
+                // the name goes into the layout constant's name
+                if !is_identifier(name) {
+                    bail!("{}: vertex buffer name {} has to be a plain identifier", src_path.display(), name);
+                }
+                let entries = words
+                    .map(|word| buffer_entry(word).with_context(|| format!("{}: {} isn't a location or a name:type", src_path.display(), word)))
+                    .collect::<Result<Vec<_>>>()?;
+                vertex_buffers.push((name.to_string(), entries));
+            }
+        }
 
         Ok(Self {
             src,
             src_path,
-            spv_path,
-            kind
+            kind,
+            flags,
+            vertex_buffers,
+            unfilterable
+        })
+    }
+
+    // shader.vert and shader.frag are both part of the "shader" pipeline
+    fn pipeline_name(&self) -> &str {
+        self.src_path.file_stem().and_then(|stem| stem.to_str()).unwrap()
+    }
+
+    // "shader.frag"
+    fn file_name(&self) -> &str {
+        self.src_path.file_name().and_then(|name| name.to_str()).unwrap()
+    }
+
+    // the defines for every combination of the flags, the first one has none of them
+    fn permutations(&self) -> Vec<Vec<&str>> {
+        (0..1u32 << self.flags.len())
+            .map(|mask| {
+                self.flags
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .map(|(_, flag)| flag.as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // shader.frag with TEXTURED and ALPHA_TEST is shader.frag.textured.alpha_test.spv
+    fn permutation_file_name(&self, defines: &[&str]) -> String {
+        let mut name = self.file_name().to_string();
+        for define in defines {
+            name.push('.');
+            name.push_str(&define.to_lowercase());
+        }
+        name.push_str(".spv");
+        name
+    }
+
+    // shader.frag with TEXTURED is SHADER_FRAG_TEXTURED in shaders.rs
+    fn constant_name(&self, defines: &[&str]) -> String {
+        let mut name: String = self
+            .file_name()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        for define in defines {
+            name.push('_');
+            name.push_str(&define.to_uppercase());
+        }
+        name
+    }
+
+    // shader.frag gets ShaderFragPermutation
+    fn permutation_type_name(&self) -> String {
+        let mut name: String = self.file_name().split(|c: char| !c.is_ascii_alphanumeric()).map(capitalize).collect();
+        name.push_str("Permutation");
+        name
+    }
+}
+
+// what comes after `#pragma name` on a line, so `#pragma permutations` doesn't count as `#pragma permutation`
+fn pragma<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let rest = line.trim().strip_prefix("#pragma")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest.trim_start().strip_prefix(name)?;
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+// letters, digits and underscores, not starting with a digit (and not just an underscore)
+fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            word != "_" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+// can't be field names, including the ones saved for later
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro",
+    "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self", "static", "struct",
+    "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where",
+    "while", "yield",
+];
+
+// a location like `5`, or `normal:vec3` for something no shader reads
+fn buffer_entry(word: &str) -> Option<BufferEntry> {
+    if let Result::Ok(location) = word.parse() {
+        return Some(BufferEntry::Location(location));
+    }
+    let colon = word.find(':')?;
+    let (name, ty) = (&word[..colon], &word[colon + 1..]);
+    if !is_identifier(name) || RUST_KEYWORDS.contains(&name) {
+        return None;
+    }
+    let (scalar, vecsize) = match ty {
+        "float" => ("f32", 1),
+        "int" => ("i32", 1),
+        "uint" => ("u32", 1),
+        _ => {
+            let (scalar, vecsize) = if let Some(vecsize) = ty.strip_prefix("vec") {
+                ("f32", vecsize)
+            } else if let Some(vecsize) = ty.strip_prefix("ivec") {
+                ("i32", vecsize)
+            } else {
+                ("u32", ty.strip_prefix("uvec")?)
+            };
+            (scalar, vecsize.parse().ok().filter(|vecsize| (2..=4).contains(vecsize))?)
+        }
+    };
+    Some(BufferEntry::Unread {
+        name: name.to_string(),
+        rust_type: rust_vector_type(scalar, vecsize),
+        size: vecsize * 4,
+    })
+}
+
+// f32 or [f32; 3]
+fn rust_vector_type(scalar: &str, vecsize: u32) -> String {
+    if vecsize == 1 {
+        scalar.to_string()
+    } else {
+        format!("[{}; {}]", scalar, vecsize)
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+// 2^6 = 64 compiles of one shader is already a lot
+const MAX_PERMUTATION_FLAGS: usize = 6;
+
+fn compile_options(defines: &[&str]) -> Result<shaderc::CompileOptions<'static>> {
+    // cloning options drops the include callback so each compile gets fresh ones
+    let mut options = shaderc::CompileOptions::new().context("couldn't make compile options")?;
+    options.set_include_callback(resolve_include);
+    for define in defines {
+        options.add_macro_definition(define, None);
+    }
+    Ok(options)
+}
+
+// src/shaders.rs pulls this in
+// every compiled shader (and every permutation) gets a Shader constant
+// a shader with permutation flags also gets a struct with a bool per flag, shader() hands back the matching constant
+fn generate_shader_module(shaders: &[ShaderData]) -> Result<String> {
+    let mut code = String::from("// generated by build.rs, edit the shaders instead\n\n");
+    let mut names = std::collections::HashSet::new();
+    for shader in shaders {
+        for defines in shader.permutations() {
+            let name = shader.constant_name(&defines);
+            if !names.insert(name.clone()) {
+                bail!("two shaders would both be called {}, {} needs a different name", name, shader.src_path.display());
+            }
+            let spv_name = shader.permutation_file_name(&defines);
+            code += &format!(
+                "pub const {}: Shader = Shader {{\n    name: \"{}\",\n    spirv: include_bytes!(concat!(env!(\"OUT_DIR\"), \"/shaders/{}\")),\n}};\n",
+                name,
+                spv_name.trim_end_matches(".spv"),
+                spv_name
+            );
+        }
+    }
+    for shader in shaders.iter().filter(|shader| !shader.flags.is_empty()) {
+        let type_name = shader.permutation_type_name();
+        let fields: Vec<String> = shader.flags.iter().map(|flag| flag.to_lowercase()).collect();
+        code += &format!("\n// the defines {} gets compiled with\n", shader.file_name());
+        code += "#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]\n";
+        code += &format!("pub struct {} {{\n", type_name);
+        for field in &fields {
+            code += &format!("    pub {}: bool,\n", field);
+        }
+        code += "}\n\n";
+        code += &format!("impl {} {{\n", type_name);
+        code += "    pub fn shader(self) -> Shader {\n";
+        let key: Vec<String> = fields.iter().map(|field| format!("self.{},", field)).collect();
+        code += &format!("        match ({}) {{\n", key.join(" "));
+        for defines in shader.permutations() {
+            let pattern: Vec<String> = shader
+                .flags
+                .iter()
+                .map(|flag| format!("{},", defines.contains(&flag.as_str())))
+                .collect();
+            code += &format!("            ({}) => {},\n", pattern.join(" "), shader.constant_name(&defines));
+        }
+        code += "        }\n    }\n}\n";
+    }
+    Ok(code)
+}
+
+
+// shared glsl lives here, `#include <camera.glsl>` only looks in here
+// `#include "foo.glsl"` looks next to the shader doing the including first
+// headers use .glsl so the globs below don't try to compile them on their own
+const INCLUDE_DIR: &str = "./src/include";
+// where the shaders get looked for, subfolders included
+const SHADER_DIR: &str = "./src";
+
+// anything deeper than this is almost certainly a header including itself without a guard
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+fn resolve_include(
+    requested: &str,
+    include_type: shaderc::IncludeType,
+    requesting: &str,
+    depth: usize,
+) -> shaderc::IncludeCallbackResult {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(format!("{} is included more than {} deep, is it missing an include guard?", requested, MAX_INCLUDE_DEPTH));
+    }
+    let mut candidates = Vec::new();
+    if let shaderc::IncludeType::Relative = include_type {
+        if let Some(dir) = Path::new(requesting).parent() {
+            candidates.push(dir.join(requested));
+        }
+    }
+    candidates.push(Path::new(INCLUDE_DIR).join(requested));
+
+    let path = candidates
+        .iter()
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("couldn't find {} included from {}", requested, requesting))?;
+    let content = read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    // edits to a header have to recompile everything that uses it
+    println!("cargo:rerun-if-changed={}", path.display());
+    // not anyhow's Ok, this one goes back to shaderc
+    Result::Ok(shaderc::ResolvedInclude {
+        resolved_name: path.display().to_string(),
+        content,
+    })
+}
+
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+// one problem shaderc found, pointing at the file it's actually in (which might be an include)
+struct Diagnostic {
+    severity: Severity,
+    file: String,
+    line: Option<usize>,
+    message: String,
+    // which permutation it came from, empty for shaders without flags
+    defines: Vec<String>,
+}
+
+#[derive(Default)]
+struct Diagnostics {
+    list: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    // glslang writes one problem a line, "src/shader.frag:12: error: 'foo' : undeclared identifier"
+    // anything that doesn't look like that still gets reported, against the shader itself
+    fn add(&mut self, output: &str, severity: Severity, shader: &ShaderData, defines: &[&str]) {
+        let mut found_any = false;
+        for line in output.lines() {
+            let (location, severity, message) = match line.find(": error: ") {
+                Some(i) => (&line[..i], Severity::Error, &line[i + ": error: ".len()..]),
+                None => match line.find(": warning: ") {
+                    Some(i) => (&line[..i], Severity::Warning, &line[i + ": warning: ".len()..]),
+                    // the "2 errors generated." summary and blank lines
+                    None => continue,
+                },
+            };
+            let (file, line_number) = match location.rfind(':') {
+                Some(colon) => match location[colon + 1..].trim().parse::<usize>().ok() {
+                    Some(number) => (&location[..colon], Some(number)),
+                    None => (location, None),
+                },
+                None => (location, None),
+            };
+            self.push(Diagnostic {
+                severity,
+                file: file.to_string(),
+                line: line_number,
+                message: message.trim().to_string(),
+                defines: defines.iter().map(|define| define.to_string()).collect(),
+            });
+            found_any = true;
+        }
+        if !found_any && !output.trim().is_empty() {
+            self.push(Diagnostic {
+                severity,
+                file: shader.src_path.display().to_string(),
+                line: None,
+                message: output.trim().to_string(),
+                defines: defines.iter().map(|define| define.to_string()).collect(),
+            });
+        }
+    }
+
+    // a mistake outside any #ifdef shows up once per permutation, only keep the first
+    fn push(&mut self, diagnostic: Diagnostic) {
+        let repeat = self.list.iter().any(|seen| {
+            (seen.severity, &seen.file, seen.line, &seen.message)
+                == (diagnostic.severity, &diagnostic.file, diagnostic.line, &diagnostic.message)
+        });
+        if !repeat {
+            self.list.push(diagnostic);
+        }
+    }
+
+    // problems that aren't from shaderc, like reflection finding the stages don't line up
+    fn error(&mut self, compiled: &CompiledShader, message: String) {
+        self.push(Diagnostic {
+            severity: Severity::Error,
+            file: compiled.shader.src_path.display().to_string(),
+            line: None,
+            message,
+            defines: compiled.defines.clone(),
+        });
+    }
+
+    // everything goes out as cargo warnings since that's the only way a build script gets text in front of
+    // people, then the build fails once if any of it was an error
+    // the list gets emptied so the next report only shows new problems
+    fn report(&mut self) -> Result<()> {
+        for diagnostic in &self.list {
+            let kind = match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            let source_line = diagnostic.line.and_then(|number| {
+                let src = read_to_string(&diagnostic.file).ok()?;
+                src.lines().nth(number.checked_sub(1)?).map(String::from)
+            });
+            // glslang doesn't give columns, but it usually quotes the thing it didn't like
+            let column = source_line
+                .as_ref()
+                .and_then(|src| {
+                    let quoted = diagnostic.message.split('\'').nth(1).filter(|quoted| !quoted.trim().is_empty())?;
+                    src.find(quoted)
+                })
+                .map_or(1, |i| i + 1);
+            let permutation = if diagnostic.defines.is_empty() {
+                String::new()
+            } else {
+                format!(" (with {})", diagnostic.defines.join(", "))
+            };
+            match diagnostic.line {
+                Some(line) => println!(
+                    "cargo:warning={}:{}:{}: {}: {}{}",
+                    diagnostic.file, line, column, kind, diagnostic.message, permutation
+                ),
+                None => println!("cargo:warning={}: {}: {}{}", diagnostic.file, kind, diagnostic.message, permutation),
+            }
+            if let (Some(line), Some(src)) = (diagnostic.line, &source_line) {
+                let gutter = line.to_string().len();
+                println!("cargo:warning= {} | {}", line, src);
+                println!("cargo:warning= {} | {}^", " ".repeat(gutter), " ".repeat(column - 1));
+            }
+        }
+        let errors = self.list.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count();
+        let warnings = self.list.len() - errors;
+        self.list.clear();
+        if errors > 0 {
+            bail!("shaders failed to compile with {} errors and {} warnings, see the warnings above", errors, warnings);
+        }
+        Ok(())
+    }
+}
+
+
+// reflection: reading the compiled spir-v back to see what each shader actually declares
+// shaders.rs gets vertex buffer layouts, bind group layouts and uniform structs made from it, and the build fails
+// when stages of the same pipeline (shader.vert and shader.frag) don't agree with each other
+struct CompiledShader<'a> {
+    shader: &'a ShaderData,
+    defines: Vec<String>,
+    spirv: Vec<u32>,
+}
+
+impl CompiledShader<'_> {
+    // "shader.frag with TEXTURED" for error messages
+    fn describe(&self) -> String {
+        if self.defines.is_empty() {
+            self.shader.file_name().to_string()
+        } else {
+            format!("{} with {}", self.shader.file_name(), self.defines.join(", "))
+        }
+    }
+
+    fn stage(&self) -> &'static str {
+        match self.shader.kind {
+            shaderc::ShaderKind::Vertex => "VERTEX",
+            shaderc::ShaderKind::Fragment => "FRAGMENT",
+            _ => "COMPUTE",
+        }
+    }
+}
+
+// everything one permutation declares
+struct Reflection {
+    inputs: Vec<Attribute>,
+    outputs: Vec<Attribute>,
+    bindings: Vec<Binding>,
+    uniforms: Vec<UniformBlock>,
+}
+
+#[derive(Clone)]
+struct Attribute {
+    location: u32,
+    name: String,
+    // the wgpu::VertexFormat variant
+    format: &'static str,
+    // what it is in a generated vertex struct
+    rust_type: String,
+    size: u32,
+}
+
+struct Binding {
+    set: u32,
+    binding: u32,
+    name: String,
+    // the wgpu::BindingType, already written out as rust
+    ty: String,
+}
+
+#[derive(Clone, PartialEq)]
+struct UniformBlock {
+    name: String,
+    size: u32,
+    fields: Vec<UniformField>,
+}
+
+#[derive(Clone, PartialEq)]
+struct UniformField {
+    name: String,
+    offset: u32,
+    size: u32,
+    rust_type: String,
+}
+
+// unfilterable is the names from the shader's `#pragma unfilterable`
+fn reflect(spirv: &[u32], unfilterable: &[String]) -> Result<Reflection> {
+    use spirv_cross::spirv::{Ast, Decoration, Module};
+    let module = Module::from_words(spirv);
+    let mut ast = Ast::<spirv_cross::glsl::Target>::parse(&module)?;
+    let resources = ast.get_shader_resources()?;
+    let types = SpirvTypes::scan(spirv);
+
+    if let Some(combined) = resources.sampled_images.first() {
+        bail!("{} is a combined sampler2D, wgpu wants a separate texture2D and sampler", combined.name);
+    }
+    if !resources.push_constant_buffers.is_empty() {
+        bail!("push constants aren't supported yet");
+    }
+
+    let mut bindings = Vec::new();
+    let mut uniforms = Vec::new();
+    for buffer in &resources.uniform_buffers {
+        let block = uniform_block(&mut ast, buffer.base_type_id)?;
+        let ty = format!(
+            "wgpu::BindingType::Buffer {{ ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: std::num::NonZeroU64::new({}) }}",
+            block.size
+        );
+        let mut buffer_binding = binding(&ast, buffer, ty)?;
+        // blocks without an instance name come back nameless
+        if buffer_binding.name.is_empty() {
+            buffer_binding.name = block.name.clone();
+        }
+        bindings.push(buffer_binding);
+        uniforms.push(block);
+    }
+    // glsl's readonly ends up on the members rather than the buffer, so these always come out read/write
+    for buffer in &resources.storage_buffers {
+        let ty = "wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only: false }, has_dynamic_offset: false, min_binding_size: None }";
+        bindings.push(binding(&ast, buffer, ty.to_string())?);
+    }
+    // spir-v doesn't say whether a texture gets filtered or whether a sampler filters, so these assume they
+    // do unless `#pragma unfilterable` names them, wgpu 0.7 doesn't check either
+    // (Rgba32Float and friends can't be filtered, a shader reading them with texelFetch wants the pragma)
+    for image in &resources.separate_images {
+        let info = types.image(image.base_type_id)?;
+        let sample_type = match (info.depth, types.scalar(info.sampled_type)?) {
+            (true, _) => "wgpu::TextureSampleType::Depth",
+            (false, Scalar::Float) if unfilterable.contains(&image.name) => "wgpu::TextureSampleType::Float { filterable: false }",
+            (false, Scalar::Float) => "wgpu::TextureSampleType::Float { filterable: true }",
+            (false, Scalar::Sint) => "wgpu::TextureSampleType::Sint",
+            (false, Scalar::Uint) => "wgpu::TextureSampleType::Uint",
+        };
+        let ty = format!(
+            "wgpu::BindingType::Texture {{ multisampled: {}, view_dimension: {}, sample_type: {} }}",
+            info.multisampled,
+            info.view_dimension()?,
+            sample_type
+        );
+        bindings.push(binding(&ast, image, ty)?);
+    }
+    for image in &resources.storage_images {
+        let info = types.image(image.base_type_id)?;
+        let access = if ast.get_decoration(image.id, Decoration::NonReadable)? != 0 {
+            "WriteOnly"
+        } else if ast.get_decoration(image.id, Decoration::NonWritable)? != 0 {
+            "ReadOnly"
+        } else {
+            "ReadWrite"
+        };
+        let ty = format!(
+            "wgpu::BindingType::StorageTexture {{ access: wgpu::StorageTextureAccess::{}, format: wgpu::TextureFormat::{}, view_dimension: {} }}",
+            access,
+            info.storage_format().with_context(|| image.name.clone())?,
+            info.view_dimension()?
+        );
+        bindings.push(binding(&ast, image, ty)?);
+    }
+    for sampler in &resources.separate_samplers {
+        let ty = format!(
+            "wgpu::BindingType::Sampler {{ comparison: false, filtering: {} }}",
+            !unfilterable.contains(&sampler.name)
+        );
+        bindings.push(binding(&ast, sampler, ty)?);
+    }
+
+    Ok(Reflection {
+        inputs: attributes(&ast, &resources.stage_inputs)?,
+        outputs: attributes(&ast, &resources.stage_outputs)?,
+        bindings,
+        uniforms,
+    })
+}
+
+fn binding(
+    ast: &spirv_cross::spirv::Ast<spirv_cross::glsl::Target>,
+    resource: &spirv_cross::spirv::Resource,
+    ty: String,
+) -> Result<Binding> {
+    use spirv_cross::spirv::{Decoration, Type};
+    let is_array = match ast.get_type(resource.type_id)? {
+        Type::Image { array } | Type::Sampler { array } | Type::Struct { array, .. } => !array.is_empty(),
+        _ => false,
+    };
+    if is_array {
+        bail!("{} is an array of bindings, those aren't supported yet", resource.name);
+    }
+    Ok(Binding {
+        set: ast.get_decoration(resource.id, Decoration::DescriptorSet)?,
+        binding: ast.get_decoration(resource.id, Decoration::Binding)?,
+        name: resource.name.clone(),
+        ty,
+    })
+}
+
+fn attributes(
+    ast: &spirv_cross::spirv::Ast<spirv_cross::glsl::Target>,
+    variables: &[spirv_cross::spirv::Resource],
+) -> Result<Vec<Attribute>> {
+    variables
+        .iter()
+        .map(|variable| {
+            let location = ast.get_decoration(variable.id, spirv_cross::spirv::Decoration::Location)?;
+            let (format, rust_type, size) = vertex_format(&ast.get_type(variable.type_id)?)
+                .with_context(|| format!("{} at location {}", variable.name, location))?;
+            Ok(Attribute {
+                location,
+                name: variable.name.clone(),
+                format,
+                rust_type,
+                size,
+            })
+        })
+        .collect()
+}
+
+fn vertex_format(ty: &spirv_cross::spirv::Type) -> Result<(&'static str, String, u32)> {
+    use spirv_cross::spirv::Type;
+    let (names, scalar, vecsize) = match ty {
+        Type::Float { vecsize, columns: 1, array } if array.is_empty() => (["Float", "Float2", "Float3", "Float4"], "f32", *vecsize),
+        Type::Int { vecsize, columns: 1, array } if array.is_empty() => (["Int", "Int2", "Int3", "Int4"], "i32", *vecsize),
+        Type::UInt { vecsize, columns: 1, array } if array.is_empty() => (["Uint", "Uint2", "Uint3", "Uint4"], "u32", *vecsize),
+        _ => bail!("only float, int and uint scalars and vectors can go between stages (a mat4 goes in as four vec4s)"),
+    };
+    Ok((names[vecsize as usize - 1], rust_vector_type(scalar, vecsize), vecsize * 4))
+}
+
+fn uniform_block(ast: &mut spirv_cross::spirv::Ast<spirv_cross::glsl::Target>, id: u32) -> Result<UniformBlock> {
+    use spirv_cross::spirv::{Decoration, Type};
+    let name = ast.get_name(id)?;
+    let member_types = match ast.get_type(id)? {
+        Type::Struct { member_types, .. } => member_types,
+        _ => bail!("uniform block {} isn't a struct", name),
+    };
+    let mut fields = Vec::new();
+    for (i, member_type) in member_types.iter().enumerate() {
+        let i = i as u32;
+        let field_name = ast.get_member_name(id, i)?;
+        let rust_type = uniform_field_type(
+            &ast.get_type(*member_type)?,
+            ast.get_member_decoration(id, i, Decoration::MatrixStride)?,
+            ast.get_decoration(*member_type, Decoration::ArrayStride)?,
+        )
+        .with_context(|| format!("{}.{}", name, field_name))?;
+        fields.push(UniformField {
+            offset: ast.get_member_decoration(id, i, Decoration::Offset)?,
+            size: ast.get_declared_struct_member_size(id, i)?,
+            name: field_name,
+            rust_type,
+        });
+    }
+    Ok(UniformBlock {
+        size: ast.get_declared_struct_size(id)?,
+        name,
+        fields,
+    })
+}
+
+// std140 pads every matrix column out to a vec4, so a mat3 is [[f32; 4]; 3]
+fn uniform_field_type(ty: &spirv_cross::spirv::Type, matrix_stride: u32, array_stride: u32) -> Result<String> {
+    use spirv_cross::spirv::Type;
+    let (scalar, vecsize, columns, array) = match ty {
+        Type::Float { vecsize, columns, array } => ("f32", *vecsize, *columns, array),
+        Type::Int { vecsize, columns, array } => ("i32", *vecsize, *columns, array),
+        Type::UInt { vecsize, columns, array } => ("u32", *vecsize, *columns, array),
+        Type::Struct { .. } => bail!("structs inside uniform blocks aren't supported yet"),
+        _ => bail!("only float, int and uint scalars, vectors and matrices are supported in uniform blocks"),
+    };
+    let (rust_type, size) = if columns > 1 {
+        if matrix_stride != 16 {
+            bail!("matrix columns are {} bytes apart, only std140's 16 is supported", matrix_stride);
+        }
+        (format!("[[{}; 4]; {}]", scalar, columns), 16 * columns)
+    } else if vecsize > 1 {
+        (format!("[{}; {}]", scalar, vecsize), 4 * vecsize)
+    } else {
+        (scalar.to_string(), 4)
+    };
+    match array.as_slice() {
+        [] => Ok(rust_type),
+        [length] if array_stride == size => Ok(format!("[{}; {}]", rust_type, length)),
+        [_] => bail!(
+            "array elements are {} bytes apart but only {} big, std140 pads arrays of scalars, vec2s and vec3s so use vec4s",
+            array_stride,
+            size
+        ),
+        _ => bail!("arrays of arrays aren't supported yet"),
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Scalar {
+    Float,
+    Sint,
+    Uint,
+}
+
+// the bits of OpTypeImage spirv_cross doesn't hand back
+struct ImageType {
+    sampled_type: u32,
+    dim: u32,
+    depth: bool,
+    arrayed: bool,
+    multisampled: bool,
+    format: u32,
+}
+
+impl ImageType {
+    fn view_dimension(&self) -> Result<&'static str> {
+        // spir-v's Dim: 0 is 1d, 1 is 2d, 2 is 3d, 3 is cube
+        Ok(match (self.dim, self.arrayed) {
+            (0, false) => "wgpu::TextureViewDimension::D1",
+            (1, false) => "wgpu::TextureViewDimension::D2",
+            (1, true) => "wgpu::TextureViewDimension::D2Array",
+            (2, false) => "wgpu::TextureViewDimension::D3",
+            (3, false) => "wgpu::TextureViewDimension::Cube",
+            (3, true) => "wgpu::TextureViewDimension::CubeArray",
+            _ => bail!("unsupported image dimension {} (arrayed {})", self.dim, self.arrayed),
+        })
+    }
+
+    // the layout qualifier on a storage image, rgba16f and so on
+    fn storage_format(&self) -> Result<&'static str> {
+        Ok(match self.format {
+            1 => "Rgba32Float",
+            2 => "Rgba16Float",
+            3 => "R32Float",
+            4 => "Rgba8Unorm",
+            5 => "Rgba8Snorm",
+            6 => "Rg32Float",
+            21 => "Rgba32Sint",
+            22 => "Rgba16Sint",
+            23 => "Rgba8Sint",
+            24 => "R32Sint",
+            25 => "Rg32Sint",
+            30 => "Rgba32Uint",
+            31 => "Rgba16Uint",
+            32 => "Rgba8Uint",
+            33 => "R32Uint",
+            0 => bail!("storage images need a format qualifier like rgba16f"),
+            other => bail!("storage image format {} isn't supported yet", other),
         })
     }
 }
 
+// a quick walk over the spir-v for the types we need
+struct SpirvTypes {
+    images: HashMap<u32, ImageType>,
+    scalars: HashMap<u32, Scalar>,
+}
+
+impl SpirvTypes {
+    fn scan(spirv: &[u32]) -> Self {
+        const OP_TYPE_INT: u32 = 21;
+        const OP_TYPE_FLOAT: u32 = 22;
+        const OP_TYPE_IMAGE: u32 = 25;
+        let mut types = Self {
+            images: HashMap::new(),
+            scalars: HashMap::new(),
+        };
+        // 5 words of header, then each instruction starts with its length and opcode
+        let mut i = 5;
+        while i < spirv.len() {
+            let length = (spirv[i] >> 16) as usize;
+            if length == 0 || i + length > spirv.len() {
+                break;
+            }
+            let operands = &spirv[i + 1..i + length];
+            match spirv[i] & 0xffff {
+                OP_TYPE_INT => {
+                    let scalar = if operands[2] == 1 { Scalar::Sint } else { Scalar::Uint };
+                    types.scalars.insert(operands[0], scalar);
+                }
+                OP_TYPE_FLOAT => {
+                    types.scalars.insert(operands[0], Scalar::Float);
+                }
+                OP_TYPE_IMAGE => {
+                    types.images.insert(
+                        operands[0],
+                        ImageType {
+                            sampled_type: operands[1],
+                            dim: operands[2],
+                            depth: operands[3] == 1,
+                            arrayed: operands[4] == 1,
+                            multisampled: operands[5] == 1,
+                            format: operands[7],
+                        },
+                    );
+                }
+                _ => {}
+            }
+            i += length;
+        }
+        types
+    }
+
+    fn image(&self, id: u32) -> Result<&ImageType> {
+        self.images.get(&id).context("couldn't find an image's type in the spir-v")
+    }
+
+    fn scalar(&self, id: u32) -> Result<Scalar> {
+        self.scalars.get(&id).copied().context("couldn't find an image's sample type in the spir-v")
+    }
+}
+
+// reflect everything, check the stages of each pipeline fit together, and write the rust side out
+fn reflect_shaders(compiled: &[CompiledShader], diagnostics: &mut Diagnostics) -> String {
+    let mut reflected = Vec::new();
+    for shader in compiled {
+        match reflect(&shader.spirv, &shader.shader.unfilterable) {
+            Result::Ok(reflection) => reflected.push((shader, reflection)),
+            Err(e) => diagnostics.error(shader, format!("{:#}", e)),
+        }
+    }
+
+    // a misspelt `#pragma unfilterable` name would quietly leave the binding filterable
+    // permutations can leave bindings out, so it only has to turn up in one of them
+    let mut checked = BTreeSet::new();
+    for (shader, _) in &reflected {
+        if !checked.insert(&shader.shader.src_path) {
+            continue;
+        }
+        for name in &shader.shader.unfilterable {
+            let found = reflected
+                .iter()
+                .filter(|(other, _)| other.shader.src_path == shader.shader.src_path)
+                .flat_map(|(_, reflection)| &reflection.bindings)
+                .any(|binding| {
+                    &binding.name == name
+                        && (binding.ty.starts_with("wgpu::BindingType::Texture ")
+                            || binding.ty.starts_with("wgpu::BindingType::Sampler "))
+                });
+            if !found {
+                diagnostics.error(shader, format!("#pragma unfilterable names {} but there's no texture or sampler called that", name));
+            }
+        }
+    }
+
+    let mut code = String::new();
+    let pipelines: BTreeSet<&str> = compiled.iter().map(|shader| shader.shader.pipeline_name()).collect();
+    for pipeline in pipelines {
+        let stages: Vec<&(&CompiledShader, Reflection)> = reflected
+            .iter()
+            .filter(|(shader, _)| shader.shader.pipeline_name() == pipeline)
+            .collect();
+        check_stage_interfaces(&stages, diagnostics);
+        code += &vertex_layouts(pipeline, &stages, diagnostics);
+        code += &bind_group_layouts(pipeline, &stages, diagnostics);
+    }
+    code += &uniform_structs(&reflected, diagnostics);
+    code
+}
+
+// everything the fragment shader reads has to be written by the vertex shader, as the same type
+fn check_stage_interfaces(stages: &[&(&CompiledShader, Reflection)], diagnostics: &mut Diagnostics) {
+    let of_kind = |stage: &'static str| stages.iter().filter(move |(shader, _)| shader.stage() == stage);
+    for (fragment, fragment_reflection) in of_kind("FRAGMENT") {
+        for (vertex, vertex_reflection) in of_kind("VERTEX") {
+            for input in &fragment_reflection.inputs {
+                match vertex_reflection.outputs.iter().find(|output| output.location == input.location) {
+                    None => diagnostics.error(
+                        fragment,
+                        format!(
+                            "reads {} from location {} but {} never writes it",
+                            input.name,
+                            input.location,
+                            vertex.describe()
+                        ),
+                    ),
+                    Some(output) if output.format != input.format => diagnostics.error(
+                        fragment,
+                        format!(
+                            "reads {} at location {} as a {} but {} writes {} there as a {}",
+                            input.name,
+                            input.location,
+                            input.format,
+                            vertex.describe(),
+                            output.name,
+                            output.format
+                        ),
+                    ),
+                    Some(_) => {}
+                }
+            }
+        }
+    }
+}
+
+// a #[repr(C)] struct and a VertexBufferLayout per `#pragma vertex_buffer`, packed tight in the order the pragma lists them
+// the fields are named after the inputs, so the vertex buffer of shader.vert is ShaderVertex with a field per input
+// a buffer called instance steps once per instance, anything else once per vertex
+fn vertex_layouts(pipeline: &str, stages: &[&(&CompiledShader, Reflection)], diagnostics: &mut Diagnostics) -> String {
+    let vertex_stages: Vec<_> = stages.iter().filter(|(shader, _)| shader.stage() == "VERTEX").collect();
+    let first = match vertex_stages.first() {
+        Some((first, _)) => *first,
+        None => return String::new(),
+    };
+    let buffers = &first.shader.vertex_buffers;
+
+    // every permutation's inputs, they might not all read the same ones
+    let mut inputs: BTreeMap<u32, Attribute> = BTreeMap::new();
+    for (shader, reflection) in &vertex_stages {
+        for input in &reflection.inputs {
+            let in_buffers = buffers
+                .iter()
+                .filter(|(_, entries)| {
+                    entries
+                        .iter()
+                        .any(|entry| matches!(entry, BufferEntry::Location(location) if *location == input.location))
+                })
+                .count();
+            if in_buffers == 0 {
+                diagnostics.error(
+                    shader,
+                    format!(
+                        "reads {} from location {} but no #pragma vertex_buffer lists that location",
+                        input.name, input.location
+                    ),
+                );
+            } else if in_buffers > 1 {
+                diagnostics.error(
+                    shader,
+                    format!("location {} is in more than one #pragma vertex_buffer", input.location),
+                );
+            }
+            match inputs.get(&input.location) {
+                Some(seen) if seen.format != input.format => diagnostics.error(
+                    shader,
+                    format!(
+                        "location {} is a {} here but a {} in another permutation",
+                        input.location, input.format, seen.format
+                    ),
+                ),
+                Some(_) => {}
+                None => {
+                    inputs.insert(input.location, input.clone());
+                }
+            }
+        }
+    }
+
+    let mut code = String::new();
+    for (buffer, entries) in buffers {
+        let mut fields = String::new();
+        let mut field_names = BTreeSet::new();
+        let mut attributes = String::new();
+        let mut offset = 0;
+        for entry in entries {
+            let (name, rust_type, size) = match entry {
+                BufferEntry::Location(location) => {
+                    let input = match inputs.get(location) {
+                        Some(input) => input,
+                        None => {
+                            diagnostics.error(
+                                first,
+                                format!("#pragma vertex_buffer {} lists location {} but nothing reads it", buffer, location),
+                            );
+                            continue;
+                        }
+                    };
+                    attributes += &format!(
+                        "        // {}\n        wgpu::VertexAttribute {{\n            offset: {},\n            shader_location: {},\n            format: wgpu::VertexFormat::{},\n        }},\n",
+                        input.name, offset, location, input.format
+                    );
+                    (&input.name, &input.rust_type, input.size)
+                }
+                BufferEntry::Unread { name, rust_type, size } => (name, rust_type, *size),
+            };
+            if RUST_KEYWORDS.contains(&name.as_str()) {
+                diagnostics.error(first, format!("{} in the {} buffer is a rust keyword, it can't be a field", name, buffer));
+            } else if !field_names.insert(name) {
+                diagnostics.error(first, format!("the {} buffer has two things called {}", buffer, name));
+            }
+            fields += &format!("    pub {}: {},\n", name, rust_type);
+            offset += size;
+        }
+        let type_name: String = pipeline
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .chain(buffer.split('_'))
+            .map(capitalize)
+            .collect();
+        let step_mode = if buffer == "instance" { "Instance" } else { "Vertex" };
+        // everything is made of 4 byte scalars so repr(C) leaves no gaps, the struct and the layout agree on every offset
+        code += &format!(
+            "\n// the {} buffer {} reads\n#[repr(C)]\n#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]\npub struct {} {{\n{}}}\n\npub const {}_{}_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {{\n    array_stride: {},\n    step_mode: wgpu::InputStepMode::{},\n    attributes: &[\n{}    ],\n}};\n",
+            buffer,
+            first.shader.file_name(),
+            type_name,
+            fields,
+            pipeline.to_uppercase(),
+            buffer.to_uppercase(),
+            offset,
+            step_mode,
+            attributes
+        );
+    }
+    code
+}
+
+// a BindGroupLayoutDescriptor per set the pipeline uses, every stage that uses a binding can see it
+fn bind_group_layouts(pipeline: &str, stages: &[&(&CompiledShader, Reflection)], diagnostics: &mut Diagnostics) -> String {
+    // (set, binding) to the binding, the stages using it and who declared it first
+    let mut entries: BTreeMap<(u32, u32), (&Binding, Vec<&str>, String)> = BTreeMap::new();
+    for (shader, reflection) in stages {
+        for binding in &reflection.bindings {
+            match entries.get_mut(&(binding.set, binding.binding)) {
+                None => {
+                    entries.insert((binding.set, binding.binding), (binding, vec![shader.stage()], shader.describe()));
+                }
+                Some((seen, _, first)) if seen.ty != binding.ty => diagnostics.error(
+                    shader,
+                    format!(
+                        "set {} binding {} ({}) is declared differently to {} in {}",
+                        binding.set, binding.binding, binding.name, seen.name, first
+                    ),
+                ),
+                Some((_, users, _)) => {
+                    if !users.contains(&shader.stage()) {
+                        users.push(shader.stage());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut code = String::new();
+    let sets: BTreeSet<u32> = entries.keys().map(|(set, _)| *set).collect();
+    for set in sets {
+        code += &format!(
+            "\n// set {} of the {} pipeline\npub const {}_SET_{}: wgpu::BindGroupLayoutDescriptor<'static> = wgpu::BindGroupLayoutDescriptor {{\n    label: Some(\"{} set {}\"),\n    entries: &[\n",
+            set,
+            pipeline,
+            pipeline.to_uppercase(),
+            set,
+            pipeline,
+            set
+        );
+        for ((_, binding), (entry, stages, _)) in entries.range((set, 0)..=(set, u32::MAX)) {
+            let visibility = if stages.len() == 1 {
+                format!("wgpu::ShaderStage::{}", stages[0])
+            } else {
+                let bits: Vec<String> = stages.iter().map(|stage| format!("wgpu::ShaderStage::{}.bits()", stage)).collect();
+                format!("wgpu::ShaderStage::from_bits_truncate({})", bits.join(" | "))
+            };
+            code += &format!(
+                "        // {}\n        wgpu::BindGroupLayoutEntry {{\n            binding: {},\n            visibility: {},\n            ty: {},\n            count: None,\n        }},\n",
+                entry.name, binding, visibility, entry.ty
+            );
+        }
+        code += "    ],\n};\n";
+    }
+    code
+}
+
+// a #[repr(C)] struct per uniform block, named after the block (Camera gets CameraUniform)
+// blocks with the same name have to be laid out the same everywhere
+fn uniform_structs(reflected: &[(&CompiledShader, Reflection)], diagnostics: &mut Diagnostics) -> String {
+    let mut blocks: BTreeMap<&str, (&UniformBlock, Vec<&str>)> = BTreeMap::new();
+    for (shader, reflection) in reflected {
+        for block in &reflection.uniforms {
+            match blocks.get_mut(block.name.as_str()) {
+                None => {
+                    blocks.insert(&block.name, (block, vec![shader.shader.file_name()]));
+                }
+                Some((seen, _)) if *seen != block => diagnostics.error(
+                    shader,
+                    format!("the {} uniform block is laid out differently here than in other shaders", block.name),
+                ),
+                Some((_, users)) => {
+                    if !users.contains(&shader.shader.file_name()) {
+                        users.push(shader.shader.file_name());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut code = String::new();
+    for (name, (block, users)) in blocks {
+        let type_name: String = name.split(|c: char| !c.is_ascii_alphanumeric()).map(capitalize).collect();
+        code += &format!(
+            "\n// the {} uniform block in {}\n#[repr(C)]\n#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]\npub struct {}Uniform {{\n",
+            name,
+            users.join(", "),
+            type_name
+        );
+        // std140 leaves gaps that rust won't, so they get filled in with padding fields
+        let mut end = 0;
+        let mut pads = 0;
+        for field in &block.fields {
+            if field.offset > end {
+                code += &format!("    pub _pad{}: [u32; {}],\n", pads, (field.offset - end) / 4);
+                pads += 1;
+            }
+            code += &format!("    pub {}: {},\n", field.name, field.rust_type);
+            end = field.offset + field.size;
+        }
+        if block.size > end {
+            code += &format!("    pub _pad{}: [u32; {}],\n", pads, (block.size - end) / 4);
+        }
+        code += "}\n";
+    }
+    code
+}
+
 
 fn main() -> Result<()> {
     // get all the shader paths
     let mut shader_paths = [
-        glob("./src/**/*.vert")?,
-        glob("./src/**/*.frag")?,
-        glob("./src/**/*.comp")?
+        glob(&format!("{}/**/*.vert", SHADER_DIR))?,
+        glob(&format!("{}/**/*.frag", SHADER_DIR))?,
+        glob(&format!("{}/**/*.comp", SHADER_DIR))?
     ];
     let shaders = shader_paths
 .iter_mut()
@@ -57,22 +1181,208 @@ fn main() -> Result<()> {
 .into_iter()
 .collect::<Result<Vec<_>>>()?; 
 
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+    let shader_dir = out_dir.join("shaders");
+    std::fs::create_dir_all(&shader_dir)?;
+
     let mut compiler = shaderc::Compiler::new().context("couldn't make compiler")?;
-    for shader in shaders {
-        //instructio for cargo to rerun if things change
-        println!("cargo:rerun-if-changed={}",shader.src_path.as_os_str().to_str().unwrap());
-
-        let compiled = compiler.compile_into_spirv(
-            &shader.src,
-            shader.kind,
-            &shader.src_path.to_str().unwrap(),
-            "main",
-            None
-        )?;
-        // write the result to a file
-        write(shader.spv_path,compiled.as_binary_u8())?;
+    let mut diagnostics = Diagnostics::default();
+    let mut compiled_shaders = Vec::new();
+    // cargo watches a directory and everything under it, so new shaders and headers showing up rerun this too
+    // (that includes every .rs file in there, compiling the shaders again is cheap enough)
+    println!("cargo:rerun-if-changed={}", SHADER_DIR);
+    for shader in &shaders {
+        // everything goes in OUT_DIR so the source tree stays clean, shaders.rs is how it gets found
+        // a shader without flags just has the one permutation with no defines
+        for defines in shader.permutations() {
+            let result = compiler.compile_into_spirv(
+                &shader.src,
+                shader.kind,
+                shader.src_path.to_str().unwrap(),
+                "main",
+                Some(&compile_options(&defines)?),
+            );
+            // keep going after a failure so one build shows every problem at once
+            match result {
+                // anyhow::Ok gets in the way of the plain Ok pattern
+                Result::Ok(compiled) => {
+                    if compiled.get_num_warnings() > 0 {
+                        diagnostics.add(&compiled.get_warning_messages(), Severity::Warning, shader, &defines);
+                    }
+                    write(shader_dir.join(shader.permutation_file_name(&defines)), compiled.as_binary_u8())?;
+                    compiled_shaders.push(CompiledShader {
+                        shader,
+                        defines: defines.iter().map(|define| define.to_string()).collect(),
+                        spirv: compiled.as_binary().to_vec(),
+                    });
+                }
+                Err(shaderc::Error::CompilationError(_, messages)) => {
+                    diagnostics.add(&messages, Severity::Error, shader, &defines)
+                }
+                Err(e) => diagnostics.add(&e.to_string(), Severity::Error, shader, &defines),
+            }
+        }
     }
+    diagnostics.report()?;
+    // only worth looking at how the stages fit together once they all compile
+    let reflected = reflect_shaders(&compiled_shaders, &mut diagnostics);
+    diagnostics.report()?;
+    write(out_dir.join("shaders.rs"), generate_shader_module(&shaders)? + &reflected)?;
+
+    compress_textures()?;
     Ok(())
 
 
-}
\ No newline at end of file
+}
+
+
+// textures get squashed into bc formats ahead of time so the app can hand them straight to the gpu
+// assets/foo.png turns into $OUT_DIR/assets/foo.dds with a full mip chain
+// which format depends on the end of the file name
+// - foo_normal.png is a normal map, linear bc7
+// - foo_bc1.png is bc1, 4 bits a pixel, no real alpha
+// - foo_bc3.png is bc3, bc1 color plus smooth alpha
+// - anything else is srgb bc7, the best looking of the lot
+#[derive(Copy, Clone, Debug, Hash)]
+enum BcFormat {
+    Bc1,
+    Bc3,
+    Bc7,
+}
+
+struct TextureJob {
+    src_path: PathBuf,
+    out_path: PathBuf,
+    format: BcFormat,
+    srgb: bool,
+}
+
+// bump this when the compression code changes so old cache entries don't get reused
+const TEXTURE_CACHE_VERSION: u32 = 1;
+
+impl TextureJob {
+    fn new(src_path: PathBuf, out_dir: &Path) -> Result<Self> {
+        let stem = src_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("texture has a weird file name")?;
+        let (format, srgb) = if stem.ends_with("_normal") {
+            (BcFormat::Bc7, false)
+        } else if stem.ends_with("_bc1") {
+            (BcFormat::Bc1, true)
+        } else if stem.ends_with("_bc3") {
+            (BcFormat::Bc3, true)
+        } else {
+            (BcFormat::Bc7, true)
+        };
+        // keep the folders the same under OUT_DIR
+        let relative = src_path.strip_prefix("./").unwrap_or(&src_path);
+        let out_path = out_dir.join(relative).with_extension("dds");
+        Ok(Self {
+            src_path,
+            out_path,
+            format,
+            srgb,
+        })
+    }
+
+    fn dxgi_format(&self) -> ddsfile::DxgiFormat {
+        use ddsfile::DxgiFormat;
+        match (self.format, self.srgb) {
+            (BcFormat::Bc1, true) => DxgiFormat::BC1_UNorm_sRGB,
+            (BcFormat::Bc1, false) => DxgiFormat::BC1_UNorm,
+            (BcFormat::Bc3, true) => DxgiFormat::BC3_UNorm_sRGB,
+            (BcFormat::Bc3, false) => DxgiFormat::BC3_UNorm,
+            (BcFormat::Bc7, true) => DxgiFormat::BC7_UNorm_sRGB,
+            (BcFormat::Bc7, false) => DxgiFormat::BC7_UNorm,
+        }
+    }
+}
+
+fn compress_textures() -> Result<()> {
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+    let cache_dir = out_dir.join("texture-cache");
+    std::fs::create_dir_all(&cache_dir)?;
+    // new files showing up should rerun this too
+    println!("cargo:rerun-if-changed=./assets");
+
+    let mut texture_paths = [glob("./assets/**/*.png")?, glob("./assets/**/*.jpg")?];
+    for path in texture_paths.iter_mut().flatten() {
+        let job = TextureJob::new(path?, &out_dir)?;
+        println!("cargo:rerun-if-changed={}", job.src_path.display());
+
+        let src = read(&job.src_path)?;
+        // the same picture compressed the same way always gives the same file, so only do the slow part once
+        let mut hasher = DefaultHasher::new();
+        TEXTURE_CACHE_VERSION.hash(&mut hasher);
+        job.format.hash(&mut hasher);
+        job.srgb.hash(&mut hasher);
+        src.hash(&mut hasher);
+        let cached = cache_dir.join(format!("{:016x}.dds", hasher.finish()));
+        if !cached.exists() {
+            let dds = compress_texture(&job, &src)
+                .with_context(|| format!("couldn't compress {}", job.src_path.display()))?;
+            write(&cached, dds)?;
+        }
+        if let Some(parent) = job.out_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&cached, &job.out_path)?;
+    }
+    Ok(())
+}
+
+// the whole mip chain, compressed and written out as a dds file
+fn compress_texture(job: &TextureJob, src: &[u8]) -> Result<Vec<u8>> {
+    let img = image::load_from_memory(src)?;
+    let (width, height) = (img.width(), img.height());
+    let mip_count = 32 - width.max(height).max(1).leading_zeros();
+
+    let mut data = Vec::new();
+    for level in 0..mip_count {
+        let level_width = (width >> level).max(1);
+        let level_height = (height >> level).max(1);
+        // every level comes from the full image so the blur doesn't stack up
+        let level_img = if level == 0 {
+            img.to_rgba8()
+        } else {
+            img.resize_exact(level_width, level_height, image::imageops::FilterType::Triangle)
+                .to_rgba8()
+        };
+        // the compressor works on whole 4x4 blocks, pad the edges out by repeating the last pixel
+        let padded_width = (level_width + 3) / 4 * 4;
+        let padded_height = (level_height + 3) / 4 * 4;
+        let padded = image::RgbaImage::from_fn(padded_width, padded_height, |x, y| {
+            *level_img.get_pixel(x.min(level_width - 1), y.min(level_height - 1))
+        });
+        let surface = intel_tex_2::RgbaSurface {
+            width: padded_width,
+            height: padded_height,
+            stride: padded_width * 4,
+            data: padded.as_raw(),
+        };
+        let blocks = match job.format {
+            BcFormat::Bc1 => intel_tex_2::bc1::compress_blocks(&surface),
+            BcFormat::Bc3 => intel_tex_2::bc3::compress_blocks(&surface),
+            BcFormat::Bc7 => intel_tex_2::bc7::compress_blocks(&intel_tex_2::bc7::alpha_basic_settings(), &surface),
+        };
+        data.extend_from_slice(&blocks);
+    }
+
+    let mut dds = ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+        height,
+        width,
+        depth: None,
+        format: job.dxgi_format(),
+        mipmap_levels: Some(mip_count),
+        array_layers: None,
+        caps2: None,
+        is_cubemap: false,
+        resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+        alpha_mode: ddsfile::AlphaMode::Unknown,
+    })?;
+    dds.data = data;
+    let mut out = Vec::new();
+    dds.write(&mut out)?;
+    Ok(out)
+}
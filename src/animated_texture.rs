@@ -0,0 +1,190 @@
+// textures that play like a flipbook, from an animated gif or a folder of numbered images
+// every frame gets converted up front, State::update moves the clock along and the current frame gets written in
+use crate::texture::{MipmapMode, Texture, TextureData, TextureOptions};
+use anyhow::*;
+use image::AnimationDecoder;
+use std::path::Path;
+use std::time::Duration;
+
+// how far [ and ] can take the speed, Duration::mul_f32 panics on an infinite one
+const MIN_SPEED: f32 = 1.0 / 64.0;
+const MAX_SPEED: f32 = 64.0;
+
+// frame rates an image sequence can play at, 1 / fps overflows Duration (and panics) for tiny ones
+// and rounds down to nothing for huge ones
+const MIN_FPS: f32 = 1.0 / 3600.0;
+const MAX_FPS: f32 = 1000.0;
+
+struct Frame {
+    data: TextureData,
+    // how long it stays up at speed 1.0
+    delay: Duration,
+}
+
+pub struct AnimatedTexture {
+    pub texture: Texture,
+    frames: Vec<Frame>,
+    current: usize,
+    // the frame that's on the gpu right now
+    uploaded: usize,
+    // how long the current frame has been showing
+    time_in_frame: Duration,
+    pub playing: bool,
+    // start over at the end, otherwise it stops on the last frame
+    pub looping: bool,
+    // 2.0 plays twice as fast, set_speed keeps it in range
+    speed: f32,
+}
+
+impl AnimatedTexture {
+    // gif frames come out of the image crate already composited into full size rgba images
+    pub fn from_gif(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], label: &str) -> Result<Self> {
+        let decoder = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(bytes))?;
+        let frames = decoder
+            .into_frames()
+            .collect_frames()?
+            .into_iter()
+            .map(|frame| {
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                let delay = Duration::from_secs_f64(numer as f64 / denom.max(1) as f64 / 1000.0);
+                // browsers treat really short delays as 100ms, plenty of gifs count on that
+                // (and a delay of 0 would never move on)
+                let delay = if delay < Duration::from_millis(20) {
+                    Duration::from_millis(100)
+                } else {
+                    delay
+                };
+                (image::DynamicImage::ImageRgba8(frame.into_buffer()), delay)
+            })
+            .collect();
+        Self::from_frames(device, queue, frames, label)
+    }
+
+    // every image in the folder, in order of the number in their names (frame_2 before frame_10)
+    pub fn from_sequence<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        folder: P,
+        fps: f32,
+        label: &str,
+    ) -> Result<Self> {
+        let folder = folder.as_ref();
+        let mut paths: Vec<_> = std::fs::read_dir(folder)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| image::ImageFormat::from_path(path).is_ok())
+            .collect();
+        paths.sort_by_key(|path| (frame_number(path), path.clone()));
+        // NaN and infinity parse as floats too, and fail this like everything else out of range
+        if !(MIN_FPS..=MAX_FPS).contains(&fps) {
+            bail!("an image sequence needs a frame rate between {} and {}, got {}", MIN_FPS, MAX_FPS, fps);
+        }
+        let delay = Duration::from_secs_f32(1.0 / fps);
+        let frames = paths
+            .iter()
+            .map(|path| {
+                let img = image::open(path).with_context(|| format!("couldn't load {}", path.display()))?;
+                Ok((img, delay))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_frames(device, queue, frames, label)
+    }
+
+    // the frames all have to be the same size, the first one shows straight away
+    pub fn from_frames(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frames: Vec<(image::DynamicImage, Duration)>,
+        label: &str,
+    ) -> Result<Self> {
+        let (first, _) = frames.first().context("an animation needs at least one frame")?;
+        // update would go round forever trying to use up the time
+        if frames.iter().any(|(_, delay)| *delay == Duration::from_secs(0)) {
+            bail!("{}: every frame of an animation has to stay up for some time", label);
+        }
+        // mips would need remaking every frame so skip them
+        let options = TextureOptions::new(label).mipmaps(MipmapMode::None);
+        let format = TextureData::preferred_format(first, options.color_space);
+        let frames = frames
+            .iter()
+            .map(|(img, delay)| {
+                Ok(Frame {
+                    data: TextureData::from_image_as(img, format, options.color_space)?,
+                    delay: *delay,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (width, height) = (frames[0].data.width, frames[0].data.height);
+        if frames.iter().any(|frame| (frame.data.width, frame.data.height) != (width, height)) {
+            bail!("{}: every frame of an animation has to be the same size", label);
+        }
+        let texture = Texture::new_empty(device, width, height, &options.format(format))?;
+        texture.write_data(queue, [0, 0], &frames[0].data)?;
+        Ok(Self {
+            texture,
+            frames,
+            current: 0,
+            uploaded: 0,
+            time_in_frame: Duration::from_secs(0),
+            playing: true,
+            looping: true,
+            speed: 1.0,
+        })
+    }
+
+    pub fn toggle_playing(&mut self) {
+        self.playing = !self.playing;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        // max turns NaN into MIN_SPEED too
+        self.speed = speed.max(MIN_SPEED).min(MAX_SPEED);
+    }
+
+    // jump straight to a frame, it gets uploaded on the next update
+    #[allow(dead_code)]
+    pub fn seek(&mut self, frame: usize) {
+        self.current = frame.min(self.frames.len() - 1);
+        self.time_in_frame = Duration::from_secs(0);
+    }
+
+    // call once a frame, uploads the new frame when it changes
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: Duration) -> Result<()> {
+        if self.playing {
+            self.time_in_frame += dt.mul_f32(self.speed);
+            while self.time_in_frame >= self.frames[self.current].delay {
+                self.time_in_frame -= self.frames[self.current].delay;
+                if self.current + 1 < self.frames.len() {
+                    self.current += 1;
+                } else if self.looping {
+                    self.current = 0;
+                } else {
+                    // stop on the last frame
+                    self.playing = false;
+                    self.time_in_frame = Duration::from_secs(0);
+                    break;
+                }
+            }
+        }
+        if self.current != self.uploaded {
+            self.texture.write_data(queue, [0, 0], &self.frames[self.current].data)?;
+            self.uploaded = self.current;
+        }
+        Ok(())
+    }
+}
+
+// the last run of digits in the file name, frames without one go first
+fn frame_number(path: &Path) -> u64 {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+    let digits: String = stem
+        .chars()
+        .rev()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.chars().rev().collect::<String>().parse().unwrap_or(0)
+}
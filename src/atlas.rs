@@ -0,0 +1,218 @@
+// packing lots of small images into one big texture so they can share a bind group
+// each image gets a UvRect saying where it ended up, instances pick their sprite with Instance::uv_rect
+use crate::texture::{MipmapMode, Texture, TextureData, TextureOptions};
+use anyhow::*;
+use image::{GenericImageView, RgbaImage};
+
+// where an image ended up, in 0..1 texture coordinates
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl UvRect {
+    // the offset x, y, scale x, y that shader.vert wants, see Instance::uv_rect
+    pub fn offset_scale(&self) -> [f32; 4] {
+        [
+            self.min[0],
+            self.min[1],
+            self.max[0] - self.min[0],
+            self.max[1] - self.min[1],
+        ]
+    }
+}
+
+// a spot in the atlas in pixels
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PixelRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct Atlas {
+    pub texture: Texture,
+    // in the order the images were added
+    pub rects: Vec<UvRect>,
+}
+
+pub struct AtlasBuilder {
+    images: Vec<RgbaImage>,
+    // empty pixels between images so filtering doesn't bleed neighbours in, filled with the edge colors
+    padding: u32,
+    max_size: u32,
+}
+
+impl AtlasBuilder {
+    // max_size is the biggest the atlas is allowed to get on either side
+    pub fn new(max_size: u32) -> Self {
+        Self {
+            images: Vec::new(),
+            padding: 2,
+            max_size,
+        }
+    }
+
+    // hands back the index of the image's rect in Atlas::rects
+    pub fn add(&mut self, img: &image::DynamicImage) -> usize {
+        self.images.push(img.to_rgba8());
+        self.images.len() - 1
+    }
+
+    // pack everything and upload it
+    // no mipmaps whatever the options say, the smaller levels would blur neighbouring images into each other
+    pub fn build(&self, device: &wgpu::Device, queue: &wgpu::Queue, options: &TextureOptions) -> Result<Atlas> {
+        let (pixels, rects) = self.pack_image()?;
+        let (width, height) = pixels.dimensions();
+        let pixels = image::DynamicImage::ImageRgba8(pixels);
+        let format = options
+            .format
+            .unwrap_or_else(|| TextureData::preferred_format(&pixels, options.color_space));
+        let options = options.clone().format(format).mipmaps(MipmapMode::None);
+        let texture = Texture::new_empty(device, width, height, &options)?;
+        texture.write_region(queue, [0, 0], &pixels)?;
+        let rects = rects
+            .iter()
+            .map(|rect| UvRect {
+                min: [rect.x as f32 / width as f32, rect.y as f32 / height as f32],
+                max: [
+                    (rect.x + rect.width) as f32 / width as f32,
+                    (rect.y + rect.height) as f32 / height as f32,
+                ],
+            })
+            .collect();
+        Ok(Atlas { texture, rects })
+    }
+
+    // the packed atlas on the cpu, plus where each image went
+    pub fn pack_image(&self) -> Result<(RgbaImage, Vec<PixelRect>)> {
+        let padded_sizes: Vec<(u32, u32)> = self
+            .images
+            .iter()
+            .map(|img| (img.width() + self.padding * 2, img.height() + self.padding * 2))
+            .collect();
+        let (width, height, slots) = pack(&padded_sizes, self.max_size).with_context(|| {
+            format!("{} images don't fit in a {}x{} atlas", self.images.len(), self.max_size, self.max_size)
+        })?;
+
+        let mut atlas = RgbaImage::new(width, height);
+        let mut rects = Vec::new();
+        for (img, slot) in self.images.iter().zip(slots) {
+            // copy the image in, then smear its edges out into the padding
+            let (img_width, img_height) = img.dimensions();
+            for y in 0..slot.height {
+                for x in 0..slot.width {
+                    let src_x = (x as i64 - self.padding as i64).max(0).min(img_width as i64 - 1) as u32;
+                    let src_y = (y as i64 - self.padding as i64).max(0).min(img_height as i64 - 1) as u32;
+                    atlas.put_pixel(slot.x + x, slot.y + y, *img.get_pixel(src_x, src_y));
+                }
+            }
+            rects.push(PixelRect {
+                x: slot.x + self.padding,
+                y: slot.y + self.padding,
+                width: img_width,
+                height: img_height,
+            });
+        }
+        Ok((atlas, rects))
+    }
+}
+
+// shelf packing, tallest first
+// rows ("shelves") get filled left to right, a new one starts when the next rect doesn't fit across
+// tries power of two widths from small to max_size and takes the first that fits
+// the rects come back in the same order as the sizes
+pub fn pack(sizes: &[(u32, u32)], max_size: u32) -> Option<(u32, u32, Vec<PixelRect>)> {
+    let area: u64 = sizes.iter().map(|&(w, h)| w as u64 * h as u64).sum();
+    let widest = sizes.iter().map(|&(w, _)| w).max().unwrap_or(1);
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].1));
+
+    // no point trying widths that can't possibly hold everything
+    let mut width = widest.max((area as f64).sqrt() as u32).max(1).next_power_of_two();
+    while width <= max_size {
+        if let Some((height, rects)) = pack_shelves(sizes, &order, width) {
+            if height <= max_size {
+                return Some((width, height.max(1), rects));
+            }
+        }
+        width *= 2;
+    }
+    None
+}
+
+fn pack_shelves(sizes: &[(u32, u32)], order: &[usize], width: u32) -> Option<(u32, Vec<PixelRect>)> {
+    let mut rects = vec![
+        PixelRect {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0
+        };
+        sizes.len()
+    ];
+    let (mut x, mut shelf_y, mut shelf_height) = (0, 0, 0);
+    for &i in order {
+        let (w, h) = sizes[i];
+        if w > width {
+            return None;
+        }
+        if x + w > width {
+            // start the next shelf under the tallest thing on this one
+            shelf_y += shelf_height;
+            x = 0;
+            shelf_height = 0;
+        }
+        rects[i] = PixelRect {
+            x,
+            y: shelf_y,
+            width: w,
+            height: h,
+        };
+        x += w;
+        shelf_height = shelf_height.max(h);
+    }
+    Some((shelf_y + shelf_height, rects))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: &PixelRect, b: &PixelRect) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn pack_keeps_rects_inside_and_apart() {
+        let sizes = [(10, 30), (64, 8), (20, 20), (5, 5), (33, 12), (1, 1), (40, 40)];
+        let (width, height, rects) = pack(&sizes, 256).unwrap();
+        assert!(width.is_power_of_two());
+        assert_eq!(rects.len(), sizes.len());
+        for (rect, &(w, h)) in rects.iter().zip(sizes.iter()) {
+            // same order and size as asked for
+            assert_eq!((rect.width, rect.height), (w, h));
+            assert!(rect.x + rect.width <= width && rect.y + rect.height <= height);
+        }
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn pack_picks_the_smallest_width_that_fits() {
+        // four 8x8 squares fit exactly in 16x16
+        let (width, height, _) = pack(&[(8, 8); 4], 64).unwrap();
+        assert_eq!((width, height), (16, 16));
+    }
+
+    #[test]
+    fn pack_fails_when_nothing_fits() {
+        assert!(pack(&[(65, 1)], 64).is_none());
+        assert!(pack(&[(64, 64), (64, 64)], 64).is_none());
+    }
+}
@@ -0,0 +1,22 @@
+#version 450
+
+// texelFetch only, so the source can be one of the float formats that can't be filtered
+#pragma unfilterable t_source s_source
+
+layout (location = 0) out vec4 f_color;
+
+// the mip level above the one we're drawing into
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+
+// average the 2x2 block of source texels under this pixel
+// srgb textures hand back linear values here and get converted back when written, so the average is right
+void main() {
+    ivec2 max_coord = textureSize(sampler2D(t_source, s_source), 0) - 1;
+    ivec2 base = ivec2(gl_FragCoord.xy) * 2;
+    vec4 sum = texelFetch(sampler2D(t_source, s_source), min(base, max_coord), 0)
+        + texelFetch(sampler2D(t_source, s_source), min(base + ivec2(1, 0), max_coord), 0)
+        + texelFetch(sampler2D(t_source, s_source), min(base + ivec2(0, 1), max_coord), 0)
+        + texelFetch(sampler2D(t_source, s_source), min(base + ivec2(1, 1), max_coord), 0);
+    f_color = sum * 0.25;
+}
@@ -0,0 +1,8 @@
+#version 450
+
+// a single triangle big enough to cover the whole target, no vertex buffer needed
+// vertex 0 -> (-1,-1), 1 -> (3,-1), 2 -> (-1,3)
+void main() {
+    vec2 pos = vec2(float((gl_VertexIndex & 1) << 2) - 1.0, float((gl_VertexIndex & 2) << 1) - 1.0);
+    gl_Position = vec4(pos, 0.0, 1.0);
+}
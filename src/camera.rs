@@ -0,0 +1,114 @@
+// the camera turns world space into clip space so the window's aspect ratio stops squashing things
+use cgmath::prelude::*;
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+
+// cgmath builds matrices for opengl where clip space z goes from -1 to 1
+// wgpu (like directx and metal) uses 0 to 1 so this squeezes z into the right range
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[derive(Copy, Clone, Debug)]
+pub enum Projection {
+    // things get smaller the further away they are
+    Perspective { fovy: Deg<f32>, znear: f32, zfar: f32 },
+    // no foreshortening, height is how much of the world fits vertically on screen
+    Orthographic { height: f32, znear: f32, zfar: f32 },
+}
+
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    // width / height of whatever we render into
+    pub aspect: f32,
+    pub projection: Projection,
+}
+
+impl Camera {
+    // looking down -z at the origin from far enough back to see the pentagon
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            eye: (0.0, 0.0, 2.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: width as f32 / height as f32,
+            projection: Projection::Perspective {
+                fovy: Deg(45.0),
+                znear: 0.1,
+                zfar: 100.0,
+            },
+        }
+    }
+
+    // call this when the window changes size
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // a minimized window reports 0x0, keep the old aspect rather than dividing by zero
+        if width > 0 && height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+
+    // switch between perspective and orthographic, keeping roughly the same framing at the target
+    pub fn toggle_projection(&mut self) {
+        let distance = (self.target - self.eye).magnitude();
+        self.projection = match self.projection {
+            Projection::Perspective { fovy, znear, zfar } => Projection::Orthographic {
+                height: 2.0 * distance * (fovy / 2.0).tan(),
+                znear,
+                zfar,
+            },
+            Projection::Orthographic { height, znear, zfar } => Projection::Perspective {
+                fovy: Deg::atan2(height / 2.0, distance) * 2.0,
+                znear,
+                zfar,
+            },
+        };
+    }
+
+    // moves the world so the camera sits at the origin looking down -z
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at(self.eye, self.target, self.up)
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        let proj = match self.projection {
+            Projection::Perspective { fovy, znear, zfar } => {
+                cgmath::perspective(fovy, self.aspect, znear, zfar)
+            }
+            Projection::Orthographic { height, znear, zfar } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * self.aspect;
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, znear, zfar)
+            }
+        };
+        OPENGL_TO_WGPU_MATRIX * proj
+    }
+}
+
+// what actually goes in the uniform buffer
+// build.rs generates the struct from the Camera block in camera.glsl so the two can't drift apart
+// cgmath matrices aren't Pod so it holds plain arrays
+pub use crate::shaders::CameraUniform;
+
+impl CameraUniform {
+    pub fn new() -> Self {
+        Self {
+            u_view: Matrix4::identity().into(),
+            u_proj: Matrix4::identity().into(),
+            u_view_proj: Matrix4::identity().into(),
+        }
+    }
+
+    pub fn update(&mut self, camera: &Camera) {
+        let view = camera.view_matrix();
+        let proj = camera.projection_matrix();
+        self.u_view = view.into();
+        self.u_proj = proj.into();
+        self.u_view_proj = (proj * view).into();
+    }
+}
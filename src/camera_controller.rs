@@ -0,0 +1,302 @@
+// ways of moving the camera around with the mouse and keyboard
+// State keeps a list of these and C cycles through them
+use crate::camera::{Camera, Projection};
+use cgmath::prelude::*;
+use cgmath::{Rad, Vector3};
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+use winit::event::*;
+
+// stop just short of straight up/down, look_at falls apart when forward lines up with up
+const SAFE_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+pub trait CameraController {
+    fn name(&self) -> &'static str;
+    // same deal as State::input, return true if the event got used
+    fn process_event(&mut self, event: &WindowEvent) -> bool;
+    // raw mouse movement from DeviceEvent::MouseMotion, keeps working when the cursor hits the edge of the window
+    fn process_mouse_motion(&mut self, _dx: f64, _dy: f64) {}
+    // move the camera based on everything we collected since last frame
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration);
+}
+
+// turns scroll wheel and trackpad events into "lines" scrolled, positive is away from the user
+fn scroll_amount(delta: &MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => *y,
+        // pixel deltas come from trackpads and are much bigger
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+    }
+}
+
+// spins the camera around the target point while the left mouse button is held, scroll zooms in and out
+pub struct OrbitController {
+    rotating: bool,
+    rotate_horizontal: f32,
+    rotate_vertical: f32,
+    scroll: f32,
+    // radians per unit of mouse motion
+    sensitivity: f32,
+}
+
+impl OrbitController {
+    pub fn new(sensitivity: f32) -> Self {
+        Self {
+            rotating: false,
+            rotate_horizontal: 0.0,
+            rotate_vertical: 0.0,
+            scroll: 0.0,
+            sensitivity,
+        }
+    }
+}
+
+impl CameraController for OrbitController {
+    fn name(&self) -> &'static str {
+        "orbit"
+    }
+
+    fn process_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.rotating = *state == ElementState::Pressed;
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += scroll_amount(delta);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.rotating {
+            self.rotate_horizontal += dx as f32;
+            self.rotate_vertical += dy as f32;
+        }
+    }
+
+    // mouse motion is already "per frame" so dt isn't needed here
+    fn update_camera(&mut self, camera: &mut Camera, _dt: Duration) {
+        // work out where the eye is around the target as yaw/pitch/distance
+        let offset = camera.eye - camera.target;
+        let mut distance = offset.magnitude();
+        let mut yaw = offset.x.atan2(offset.z);
+        let mut pitch = (offset.y / distance).asin();
+
+        yaw -= self.rotate_horizontal * self.sensitivity;
+        pitch += self.rotate_vertical * self.sensitivity;
+        pitch = pitch.max(-SAFE_PITCH).min(SAFE_PITCH);
+        // each line of scrolling gets us 10% closer
+        distance = (distance * 0.9f32.powf(self.scroll)).max(0.01);
+
+        let (sin_yaw, cos_yaw) = yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = pitch.sin_cos();
+        camera.eye = camera.target
+            + Vector3::new(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw) * distance;
+
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+        self.scroll = 0.0;
+    }
+}
+
+// WASD moves, space/shift go up and down, hold the right mouse button to look around
+pub struct FlyController {
+    forward: f32,
+    backward: f32,
+    left: f32,
+    right: f32,
+    up: f32,
+    down: f32,
+    looking: bool,
+    rotate_horizontal: f32,
+    rotate_vertical: f32,
+    // world units per second
+    speed: f32,
+    sensitivity: f32,
+}
+
+impl FlyController {
+    pub fn new(speed: f32, sensitivity: f32) -> Self {
+        Self {
+            forward: 0.0,
+            backward: 0.0,
+            left: 0.0,
+            right: 0.0,
+            up: 0.0,
+            down: 0.0,
+            looking: false,
+            rotate_horizontal: 0.0,
+            rotate_vertical: 0.0,
+            speed,
+            sensitivity,
+        }
+    }
+}
+
+impl CameraController for FlyController {
+    fn name(&self) -> &'static str {
+        "fly"
+    }
+
+    fn process_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                // 1 while held, 0 once let go
+                let amount = if *state == ElementState::Pressed { 1.0 } else { 0.0 };
+                match keycode {
+                    VirtualKeyCode::W | VirtualKeyCode::Up => self.forward = amount,
+                    VirtualKeyCode::S | VirtualKeyCode::Down => self.backward = amount,
+                    VirtualKeyCode::A | VirtualKeyCode::Left => self.left = amount,
+                    VirtualKeyCode::D | VirtualKeyCode::Right => self.right = amount,
+                    VirtualKeyCode::Space => self.up = amount,
+                    VirtualKeyCode::LShift => self.down = amount,
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => {
+                self.looking = *state == ElementState::Pressed;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.looking {
+            self.rotate_horizontal += dx as f32;
+            self.rotate_vertical += dy as f32;
+        }
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+
+        // turn the current view direction into yaw/pitch so we can spin it
+        let direction = (camera.target - camera.eye).normalize();
+        let mut yaw = direction.z.atan2(direction.x);
+        let mut pitch = direction.y.asin();
+        yaw += self.rotate_horizontal * self.sensitivity;
+        pitch -= self.rotate_vertical * self.sensitivity;
+        pitch = pitch.max(-SAFE_PITCH).min(SAFE_PITCH);
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+
+        let (sin_yaw, cos_yaw) = yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = pitch.sin_cos();
+        let forward = Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw);
+        let right = forward.cross(camera.up).normalize();
+
+        let velocity = forward * (self.forward - self.backward)
+            + right * (self.right - self.left)
+            + camera.up * (self.up - self.down);
+        camera.eye += velocity * self.speed * dt;
+        // keep the target a fixed step in front so look_at always has a direction
+        camera.target = camera.eye + forward;
+    }
+}
+
+// for looking at flat things, drag with the left mouse button to slide around and scroll to zoom
+pub struct PanZoomController {
+    panning: bool,
+    pan_horizontal: f32,
+    pan_vertical: f32,
+    scroll: f32,
+    // fraction of the visible height moved per unit of mouse motion
+    sensitivity: f32,
+}
+
+impl PanZoomController {
+    pub fn new(sensitivity: f32) -> Self {
+        Self {
+            panning: false,
+            pan_horizontal: 0.0,
+            pan_vertical: 0.0,
+            scroll: 0.0,
+            sensitivity,
+        }
+    }
+}
+
+impl CameraController for PanZoomController {
+    fn name(&self) -> &'static str {
+        "pan/zoom"
+    }
+
+    fn process_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.panning = *state == ElementState::Pressed;
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += scroll_amount(delta);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.panning {
+            self.pan_horizontal += dx as f32;
+            self.pan_vertical += dy as f32;
+        }
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, _dt: Duration) {
+        let offset = camera.eye - camera.target;
+        let distance = offset.magnitude();
+        let forward = -offset / distance;
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+
+        // how much of the world is visible vertically, so panning speed matches the zoom level
+        let visible_height = match camera.projection {
+            Projection::Perspective { fovy, .. } => 2.0 * distance * (Rad::from(fovy) / 2.0).tan(),
+            Projection::Orthographic { height, .. } => height,
+        };
+        // dragging moves the scene with the mouse, so the camera goes the other way
+        let pan = (right * -self.pan_horizontal + up * self.pan_vertical)
+            * self.sensitivity
+            * visible_height;
+        camera.eye += pan;
+        camera.target += pan;
+
+        // each line of scrolling zooms in 10%
+        let zoom = 0.9f32.powf(self.scroll);
+        match &mut camera.projection {
+            Projection::Orthographic { height, .. } => *height *= zoom,
+            Projection::Perspective { .. } => {
+                camera.eye = camera.target + offset * zoom;
+            }
+        }
+
+        self.pan_horizontal = 0.0;
+        self.pan_vertical = 0.0;
+        self.scroll = 0.0;
+    }
+}
@@ -0,0 +1,204 @@
+// texture files that aren't just pictures
+// ktx2 and dds hold data that's already in a gpu format (usually bc compressed) with the mips baked in,
+// so it goes up exactly as stored. openexr is float pixels like radiance .hdr (that one's in texture.rs)
+use crate::texture::TextureData;
+use anyhow::*;
+
+pub enum Container {
+    Ktx2,
+    Dds,
+    Exr,
+}
+
+// everything here starts with a magic number
+pub fn detect(bytes: &[u8]) -> Option<Container> {
+    const KTX2: &[u8] = &[0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+    if bytes.starts_with(KTX2) {
+        Some(Container::Ktx2)
+    } else if bytes.starts_with(b"DDS ") {
+        Some(Container::Dds)
+    } else if bytes.starts_with(&[0x76, 0x2F, 0x31, 0x01]) {
+        Some(Container::Exr)
+    } else {
+        None
+    }
+}
+
+// how many bytes one layer of a level takes up, compressed formats round up to whole blocks
+fn level_size(format: wgpu::TextureFormat, width: u32, height: u32) -> usize {
+    let info = format.describe();
+    let (block_width, block_height) = (info.block_dimensions.0 as u32, info.block_dimensions.1 as u32);
+    let blocks_across = (width + block_width - 1) / block_width;
+    let blocks_down = (height + block_height - 1) / block_height;
+    (blocks_across * blocks_down) as usize * info.block_size as usize
+}
+
+// one TextureData per mip level, each with all the layers (and cube faces) in it
+pub fn load_ktx2(bytes: &[u8]) -> Result<Vec<TextureData>> {
+    let reader = ktx2::Reader::new(bytes).context("couldn't read ktx2 file")?;
+    let header = reader.header();
+    if header.supercompression_scheme.is_some() {
+        bail!("supercompressed ktx2 files (basis, zstd) aren't supported, save it without supercompression");
+    }
+    if header.pixel_depth > 1 {
+        bail!("3d ktx2 textures aren't supported");
+    }
+    let format = header
+        .format
+        .context("ktx2 file has no vulkan format (basis universal?)")
+        .and_then(ktx2_format)?;
+    // cube faces just count as more layers for now
+    let layers = header.layer_count.max(1) * header.face_count.max(1);
+    reader
+        .levels()
+        .enumerate()
+        .map(|(level, level_data)| {
+            let width = (header.pixel_width >> level).max(1);
+            let height = (header.pixel_height >> level).max(1);
+            let expected = level_size(format, width, height) * layers as usize;
+            if level_data.len() != expected {
+                bail!("ktx2 level {} is {} bytes, expected {}", level, level_data.len(), expected);
+            }
+            Ok(TextureData {
+                format,
+                width,
+                height,
+                layers,
+                bytes: level_data.to_vec(),
+            })
+        })
+        .collect()
+}
+
+fn ktx2_format(format: ktx2::Format) -> Result<wgpu::TextureFormat> {
+    use ktx2::Format as K;
+    use wgpu::TextureFormat as W;
+    Ok(match format {
+        K::BC1_RGBA_UNORM_BLOCK | K::BC1_RGB_UNORM_BLOCK => W::Bc1RgbaUnorm,
+        K::BC1_RGBA_SRGB_BLOCK | K::BC1_RGB_SRGB_BLOCK => W::Bc1RgbaUnormSrgb,
+        K::BC2_UNORM_BLOCK => W::Bc2RgbaUnorm,
+        K::BC2_SRGB_BLOCK => W::Bc2RgbaUnormSrgb,
+        K::BC3_UNORM_BLOCK => W::Bc3RgbaUnorm,
+        K::BC3_SRGB_BLOCK => W::Bc3RgbaUnormSrgb,
+        K::BC4_UNORM_BLOCK => W::Bc4RUnorm,
+        K::BC4_SNORM_BLOCK => W::Bc4RSnorm,
+        K::BC5_UNORM_BLOCK => W::Bc5RgUnorm,
+        K::BC5_SNORM_BLOCK => W::Bc5RgSnorm,
+        K::BC6H_UFLOAT_BLOCK => W::Bc6hRgbUfloat,
+        K::BC6H_SFLOAT_BLOCK => W::Bc6hRgbSfloat,
+        K::BC7_UNORM_BLOCK => W::Bc7RgbaUnorm,
+        K::BC7_SRGB_BLOCK => W::Bc7RgbaUnormSrgb,
+        K::R8_UNORM => W::R8Unorm,
+        K::R8G8_UNORM => W::Rg8Unorm,
+        K::R8G8B8A8_UNORM => W::Rgba8Unorm,
+        K::R8G8B8A8_SRGB => W::Rgba8UnormSrgb,
+        K::B8G8R8A8_UNORM => W::Bgra8Unorm,
+        K::B8G8R8A8_SRGB => W::Bgra8UnormSrgb,
+        K::R16G16B16A16_SFLOAT => W::Rgba16Float,
+        K::R32G32B32A32_SFLOAT => W::Rgba32Float,
+        other => bail!("ktx2 format {:?} isn't supported", other),
+    })
+}
+
+// dds keeps each layer's whole mip chain together, the gpu wants each level's layers together so it gets shuffled
+pub fn load_dds(bytes: &[u8]) -> Result<Vec<TextureData>> {
+    let dds = ddsfile::Dds::read(&mut std::io::Cursor::new(bytes)).context("couldn't read dds file")?;
+    let format = dds_format(&dds)?;
+    let (width, height) = (dds.get_width(), dds.get_height());
+    let level_count = dds.get_num_mipmap_levels().max(1);
+    let layers = dds.get_num_array_layers().max(1);
+
+    let mut levels: Vec<TextureData> = (0..level_count)
+        .map(|level| TextureData {
+            format,
+            width: (width >> level).max(1),
+            height: (height >> level).max(1),
+            layers,
+            bytes: Vec::new(),
+        })
+        .collect();
+    for layer in 0..layers {
+        let mut data = dds.get_data(layer).context("dds file is missing a layer")?;
+        for level in levels.iter_mut() {
+            let size = level_size(format, level.width, level.height);
+            if data.len() < size {
+                bail!("dds file is too short for its mip levels");
+            }
+            level.bytes.extend_from_slice(&data[..size]);
+            data = &data[size..];
+        }
+    }
+    Ok(levels)
+}
+
+fn dds_format(dds: &ddsfile::Dds) -> Result<wgpu::TextureFormat> {
+    use ddsfile::{D3DFormat, DxgiFormat};
+    use wgpu::TextureFormat as W;
+    // newer files have a dx10 header with a dxgi format, older ones use the d3d9 fourcc codes
+    if let Some(format) = dds.get_dxgi_format() {
+        return Ok(match format {
+            DxgiFormat::BC1_UNorm => W::Bc1RgbaUnorm,
+            DxgiFormat::BC1_UNorm_sRGB => W::Bc1RgbaUnormSrgb,
+            DxgiFormat::BC2_UNorm => W::Bc2RgbaUnorm,
+            DxgiFormat::BC2_UNorm_sRGB => W::Bc2RgbaUnormSrgb,
+            DxgiFormat::BC3_UNorm => W::Bc3RgbaUnorm,
+            DxgiFormat::BC3_UNorm_sRGB => W::Bc3RgbaUnormSrgb,
+            DxgiFormat::BC4_UNorm => W::Bc4RUnorm,
+            DxgiFormat::BC4_SNorm => W::Bc4RSnorm,
+            DxgiFormat::BC5_UNorm => W::Bc5RgUnorm,
+            DxgiFormat::BC5_SNorm => W::Bc5RgSnorm,
+            DxgiFormat::BC6H_UF16 => W::Bc6hRgbUfloat,
+            DxgiFormat::BC6H_SF16 => W::Bc6hRgbSfloat,
+            DxgiFormat::BC7_UNorm => W::Bc7RgbaUnorm,
+            DxgiFormat::BC7_UNorm_sRGB => W::Bc7RgbaUnormSrgb,
+            DxgiFormat::R8_UNorm => W::R8Unorm,
+            DxgiFormat::R8G8_UNorm => W::Rg8Unorm,
+            DxgiFormat::R8G8B8A8_UNorm => W::Rgba8Unorm,
+            DxgiFormat::R8G8B8A8_UNorm_sRGB => W::Rgba8UnormSrgb,
+            DxgiFormat::B8G8R8A8_UNorm => W::Bgra8Unorm,
+            DxgiFormat::B8G8R8A8_UNorm_sRGB => W::Bgra8UnormSrgb,
+            DxgiFormat::R16G16B16A16_Float => W::Rgba16Float,
+            DxgiFormat::R32G32B32A32_Float => W::Rgba32Float,
+            other => bail!("dds format {:?} isn't supported", other),
+        });
+    }
+    match dds.get_d3d_format() {
+        // old files don't say whether they're srgb, assume colors since that's what they're mostly used for
+        Some(D3DFormat::DXT1) => Ok(W::Bc1RgbaUnormSrgb),
+        Some(D3DFormat::DXT3) => Ok(W::Bc2RgbaUnormSrgb),
+        Some(D3DFormat::DXT5) => Ok(W::Bc3RgbaUnormSrgb),
+        Some(D3DFormat::A8B8G8R8) => Ok(W::Rgba8UnormSrgb),
+        Some(D3DFormat::A8R8G8B8) => Ok(W::Bgra8UnormSrgb),
+        other => bail!("dds format {:?} isn't supported", other),
+    }
+}
+
+// exr is linear floats like hdr, missing alpha counts as opaque
+// format has to be Rgba16Float or Rgba32Float
+pub fn load_exr(bytes: &[u8], format: wgpu::TextureFormat) -> Result<TextureData> {
+    use exr::prelude::*;
+    let image = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .specific_channels()
+        .required("R")
+        .required("G")
+        .required("B")
+        .optional("A", 1.0_f32)
+        .collect_pixels(
+            |resolution: Vec2<usize>, _| {
+                (resolution, vec![[0.0_f32; 4]; resolution.width() * resolution.height()])
+            },
+            |(resolution, pixels): &mut (Vec2<usize>, Vec<[f32; 4]>),
+             position: Vec2<usize>,
+             (r, g, b, a): (f32, f32, f32, f32)| {
+                pixels[position.y() * resolution.width() + position.x()] = [r, g, b, a];
+            },
+        )
+        .first_valid_layer()
+        .all_attributes()
+        .from_buffered(std::io::Cursor::new(bytes))
+        .context("couldn't read exr file")?;
+    let (resolution, pixels) = image.layer_data.channel_data.pixels;
+    TextureData::from_rgba_floats(resolution.width() as u32, resolution.height() as u32, &pixels, format)
+}
@@ -0,0 +1,157 @@
+// cubemaps are six square faces stored as a 6 layer texture, viewed with TextureViewDimension::Cube
+// so shaders can look things up by direction instead of uv, that's what the skybox needs
+use crate::mipmap::MipmapGenerator;
+use crate::shaders;
+use crate::texture::{ColorSpace, MipmapMode, Texture, TextureOptions};
+use anyhow::*;
+use image::GenericImageView;
+
+// the format the equirect conversion writes, floats so hdr skies keep their brightness
+const CUBE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// the usual file names for the six faces, in the order the gpu wants them
+pub const FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+// six square images of the same size, +x, -x, +y, -y, +z, -z
+pub fn from_faces(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    faces: &[image::DynamicImage],
+    options: &TextureOptions,
+) -> Result<Texture> {
+    if faces.len() != 6 {
+        bail!("a cubemap needs 6 faces, got {}", faces.len());
+    }
+    let (width, height) = faces[0].dimensions();
+    if width != height {
+        bail!("cubemap faces have to be square");
+    }
+    let mut texture = Texture::from_layers(device, queue, faces, options)?;
+    texture.view = cube_view(&texture.texture);
+    Ok(texture)
+}
+
+// load the faces from a folder holding px.png, nx.png and so on (any extension the image crate reads)
+pub fn from_folder<P: AsRef<std::path::Path>>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    folder: P,
+    label: &str,
+) -> Result<Texture> {
+    let folder = folder.as_ref();
+    let mut faces = Vec::new();
+    for name in FACE_NAMES.iter() {
+        let path = std::fs::read_dir(folder)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .find(|path| path.file_stem().map_or(false, |stem| stem == *name))
+            .with_context(|| format!("{} has no {} face", folder.display(), name))?;
+        faces.push(image::open(&path).with_context(|| format!("couldn't load {}", path.display()))?);
+    }
+    from_faces(device, queue, &faces, &TextureOptions::new(label))
+}
+
+// an equirectangular panorama (the usual 2:1 hdr environment map) turned into a cube on the gpu
+pub fn from_equirect(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mip_generator: &MipmapGenerator,
+    bytes: &[u8],
+    label: &str,
+) -> Result<Texture> {
+    // hdr and exr come in as Rgba16Float, which filters fine
+    let source_options = TextureOptions::new(label)
+        .color_space(ColorSpace::Srgb)
+        .mipmaps(MipmapMode::None);
+    let source = Texture::from_bytes_with_options(device, queue, mip_generator, bytes, &source_options)?;
+    // four faces go around the panorama, so a quarter of its width keeps about the same detail
+    let face_size = (source.size.width / 4).max(1);
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: face_size,
+            height: face_size,
+            depth: 6,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: CUBE_FORMAT,
+        // the compute shader writes it, the skybox reads it
+        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::STORAGE,
+    });
+    // storage textures can't be cube views, the shader sees the faces as an array
+    let storage_view = texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("equirect target"),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&shaders::EQUIRECT_SET_0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("equirect bind group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&source.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&source.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&storage_view),
+            },
+        ],
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("equirect pipeline layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let module = device.create_shader_module(&shaders::EQUIRECT_COMP.module());
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("equirect pipeline"),
+        layout: Some(&layout),
+        module: &module,
+        entry_point: "main",
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("equirect encoder"),
+    });
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("equirect pass"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        // 8x8 texels per workgroup (see local_size in the shader), one layer of workgroups per face
+        let workgroups = (face_size + 7) / 8;
+        compute_pass.dispatch(workgroups, workgroups, 6);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+
+    Ok(Texture {
+        view: cube_view(&texture),
+        // the default linear filtering and clamped edges, the skybox samples it by direction
+        sampler: Texture::create_sampler(device, &TextureOptions::new(label), 1)?,
+        texture,
+        format: CUBE_FORMAT,
+        size: wgpu::Extent3d {
+            width: face_size,
+            height: face_size,
+            depth: 6,
+        },
+    })
+}
+
+pub fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("cube view"),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
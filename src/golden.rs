@@ -0,0 +1,142 @@
+// golden image tests, render headlessly and compare against the pictures checked in under tests/golden
+// run with GOLDEN_BLESS=1 to write the references (for a new test, or after an intentional change), then commit them
+// GOLDEN_TOLERANCE=n overrides how far apart a channel can be before the pixel counts as different
+// they draw with a software adapter (lavapipe/llvmpipe), machines without one skip them
+use crate::{readback, State};
+use anyhow::*;
+use std::path::PathBuf;
+
+// software rasterizers and gpus round slightly differently, this covers that
+const DEFAULT_TOLERANCE: u8 = 2;
+
+struct Comparison {
+    mismatched: usize,
+    max_difference: u8,
+    // red where the pixels differ, a faded copy of the expected image everywhere else
+    diff: image::RgbaImage,
+}
+
+fn compare(actual: &image::RgbaImage, expected: &image::RgbaImage, tolerance: u8) -> Result<Comparison> {
+    ensure!(
+        actual.dimensions() == expected.dimensions(),
+        "size mismatch, got {:?} expected {:?}",
+        actual.dimensions(),
+        expected.dimensions()
+    );
+    let mut mismatched = 0;
+    let mut max_difference = 0;
+    let mut diff = image::RgbaImage::new(expected.width(), expected.height());
+    for (x, y, expected_pixel) in expected.enumerate_pixels() {
+        let actual_pixel = actual.get_pixel(x, y);
+        let difference = expected_pixel
+            .0
+            .iter()
+            .zip(actual_pixel.0.iter())
+            .map(|(a, b)| (*a as i16 - *b as i16).abs() as u8)
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+        let diff_pixel = if difference > tolerance {
+            mismatched += 1;
+            [255, 0, 0, 255]
+        } else {
+            let [r, g, b, _] = expected_pixel.0;
+            [r / 4, g / 4, b / 4, 255]
+        };
+        diff.put_pixel(x, y, image::Rgba(diff_pixel));
+    }
+    Ok(Comparison {
+        mismatched,
+        max_difference,
+        diff,
+    })
+}
+
+fn check_golden(name: &str, actual: &image::RgbaImage, tolerance: u8) -> Result<()> {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let expected_path = manifest_dir.join("tests/golden").join(format!("{}.png", name));
+    let tolerance = match std::env::var("GOLDEN_TOLERANCE") {
+        Result::Ok(value) => value.parse().context("GOLDEN_TOLERANCE should be a number 0-255")?,
+        Err(_) => tolerance,
+    };
+
+    // a bless run writes the reference instead of comparing
+    if std::env::var_os("GOLDEN_BLESS").is_some() {
+        std::fs::create_dir_all(expected_path.parent().unwrap())?;
+        readback::save_image(actual, &expected_path)?;
+        eprintln!("wrote golden image {}", expected_path.display());
+        return Ok(());
+    }
+    // a missing reference fails, otherwise a clean checkout would pass without comparing anything
+    if !expected_path.exists() {
+        bail!(
+            "{}: no golden image at {}, run with GOLDEN_BLESS=1 to make one and commit it",
+            name,
+            expected_path.display()
+        );
+    }
+
+    let expected = image::open(&expected_path)?.to_rgba8();
+    let comparison = compare(actual, &expected, tolerance)?;
+    if comparison.mismatched > 0 {
+        let out_dir = manifest_dir.join("target/golden-diffs");
+        std::fs::create_dir_all(&out_dir)?;
+        let actual_path = out_dir.join(format!("{}.actual.png", name));
+        let diff_path = out_dir.join(format!("{}.diff.png", name));
+        readback::save_image(actual, &actual_path)?;
+        readback::save_image(&comparison.diff, &diff_path)?;
+        bail!(
+            "{}: {} pixels differ by more than {} (max difference {}), see {} and {}",
+            name,
+            comparison.mismatched,
+            tolerance,
+            comparison.max_difference,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+    Ok(())
+}
+
+// render with the software adapter so every machine draws the same thing
+// None when there isn't one, see State::for_test
+fn render_headless(width: u32, height: u32) -> Option<image::RgbaImage> {
+    let mut state = State::for_test(width, height)?;
+    Some(state.capture().unwrap())
+}
+
+#[test]
+fn textured_pentagon() {
+    let frame = match render_headless(256, 256) {
+        Some(frame) => frame,
+        None => return,
+    };
+    check_golden("textured_pentagon", &frame, DEFAULT_TOLERANCE).unwrap();
+}
+
+#[test]
+fn compare_allows_differences_within_tolerance() {
+    let expected = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+    let actual = image::RgbaImage::from_pixel(4, 4, image::Rgba([102, 99, 100, 255]));
+    let comparison = compare(&actual, &expected, 2).unwrap();
+    assert_eq!(comparison.mismatched, 0);
+    assert_eq!(comparison.max_difference, 2);
+}
+
+#[test]
+fn compare_marks_mismatched_pixels_in_diff() {
+    let expected = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(1, 2, image::Rgba([200, 100, 100, 255]));
+    let comparison = compare(&actual, &expected, 2).unwrap();
+    assert_eq!(comparison.mismatched, 1);
+    assert_eq!(comparison.diff.get_pixel(1, 2), &image::Rgba([255, 0, 0, 255]));
+    assert_eq!(comparison.diff.get_pixel(0, 0), &image::Rgba([25, 25, 25, 255]));
+}
+
+#[test]
+fn compare_rejects_different_sizes() {
+    let expected = image::RgbaImage::new(4, 4);
+    let actual = image::RgbaImage::new(4, 3);
+    assert!(compare(&actual, &expected, 0).is_err());
+}
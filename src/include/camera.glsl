@@ -0,0 +1,12 @@
+// the camera block, bound at set 1 by every pipeline that draws in the world
+// build.rs turns this into CameraUniform (see camera.rs), so changes here show up there
+#ifndef CAMERA_GLSL
+#define CAMERA_GLSL
+
+layout(set = 1, binding = 0) uniform Camera {
+    mat4 u_view;
+    mat4 u_proj;
+    mat4 u_view_proj;
+};
+
+#endif
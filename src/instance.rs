@@ -0,0 +1,198 @@
+// drawing lots of copies of the same mesh in one draw call
+// each copy gets its own transform (and tint) from a second vertex buffer that steps once per instance
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Quaternion, Vector3};
+use std::collections::HashMap;
+use std::ops::Range;
+use wgpu::util::DeviceExt;
+
+// the whole texture, no offset and no scaling
+pub const FULL_UV_RECT: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+#[derive(Copy, Clone, Debug)]
+pub struct Instance {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    // multiplied with the texture color in shader.frag, white leaves it alone
+    pub tint: [f32; 4],
+    // which part of the texture to use, offset x, y then scale x, y (see atlas::UvRect)
+    pub uv_rect: [f32; 4],
+}
+
+impl Instance {
+    // an instance sitting at the given spot with no rotation, scaling or tint
+    pub fn at(position: Vector3<f32>) -> Self {
+        Self {
+            position,
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            tint: [1.0; 4],
+            uv_rect: FULL_UV_RECT,
+        }
+    }
+
+    pub fn to_raw(&self) -> InstanceRaw {
+        // scale first, then rotate, then move into place
+        let model = Matrix4::from_translation(self.position)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
+        InstanceRaw {
+            uv_rect: self.uv_rect,
+            ..InstanceRaw::new(model, self.tint)
+        }
+    }
+}
+
+// the gpu side of an Instance
+// generated by build.rs from the `#pragma vertex_buffer instance` line in shader.vert, a field per input
+pub use crate::shaders::ShaderInstance as InstanceRaw;
+
+impl InstanceRaw {
+    // for when we already have the whole transform, like nodes in a gltf scene
+    pub fn new(model: Matrix4<f32>, tint: [f32; 4]) -> Self {
+        // a mat4 goes into the shader as four vec4 columns
+        let [model_matrix_0, model_matrix_1, model_matrix_2, model_matrix_3]: [[f32; 4]; 4] = model.into();
+        Self {
+            model_matrix_0,
+            model_matrix_1,
+            model_matrix_2,
+            model_matrix_3,
+            tint,
+            uv_rect: FULL_UV_RECT,
+        }
+    }
+}
+
+// what add() hands back, stays valid when other instances get removed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceId(u64);
+
+// keeps the instances packed together in a vertex buffer and only reuploads the ones that changed
+pub struct InstanceBuffer {
+    instances: Vec<Instance>,
+    // which id lives in each slot, and the other way around
+    ids: Vec<InstanceId>,
+    slots: HashMap<InstanceId, usize>,
+    next_id: u64,
+    buffer: wgpu::Buffer,
+    // how many instances fit in the buffer before it has to grow
+    capacity: usize,
+    // slots that changed since the last upload
+    dirty: Option<Range<usize>>,
+}
+
+impl InstanceBuffer {
+    pub fn new(device: &wgpu::Device, instances: Vec<Instance>) -> Self {
+        let capacity = instances.len().max(1);
+        let buffer = Self::create_buffer(device, &instances, capacity);
+        let ids: Vec<InstanceId> = (0..instances.len() as u64).map(InstanceId).collect();
+        let slots = ids.iter().enumerate().map(|(slot, id)| (*id, slot)).collect();
+        Self {
+            next_id: instances.len() as u64,
+            instances,
+            ids,
+            slots,
+            buffer,
+            capacity,
+            dirty: None,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, instances: &[Instance], capacity: usize) -> wgpu::Buffer {
+        let mut data: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+        // fill up the rest so the buffer is big enough for later adds
+        data.resize(capacity, bytemuck::Zeroable::zeroed());
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Instance Buffer"),
+            contents: bytemuck::cast_slice(&data),
+            // COPY_DST so we can write the changed parts later
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        })
+    }
+
+    fn mark_dirty(&mut self, slot: usize) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(range) => range.start.min(slot)..range.end.max(slot + 1),
+            None => slot..slot + 1,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn add(&mut self, instance: Instance) -> InstanceId {
+        let id = InstanceId(self.next_id);
+        self.next_id += 1;
+        let slot = self.instances.len();
+        self.instances.push(instance);
+        self.ids.push(id);
+        self.slots.insert(id, slot);
+        self.mark_dirty(slot);
+        id
+    }
+
+    // the last instance moves into the hole so the buffer stays packed
+    pub fn remove(&mut self, id: InstanceId) -> Option<Instance> {
+        let slot = self.slots.remove(&id)?;
+        let removed = self.instances.swap_remove(slot);
+        self.ids.swap_remove(slot);
+        if slot < self.instances.len() {
+            let moved = self.ids[slot];
+            self.slots.insert(moved, slot);
+            self.mark_dirty(slot);
+        }
+        Some(removed)
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, id: InstanceId) -> Option<&Instance> {
+        self.slots.get(&id).map(|slot| &self.instances[*slot])
+    }
+
+    // change an instance in place, it gets reuploaded on the next upload()
+    #[allow(dead_code)]
+    pub fn update<F: FnOnce(&mut Instance)>(&mut self, id: InstanceId, f: F) -> bool {
+        match self.slots.get(&id) {
+            Some(&slot) => {
+                f(&mut self.instances[slot]);
+                self.mark_dirty(slot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // the ids in the order they sit in the buffer
+    pub fn ids(&self) -> &[InstanceId] {
+        &self.ids
+    }
+
+    // send whatever changed to the gpu, call this once a frame before drawing
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.instances.len() > self.capacity {
+            // out of room, double the size and send everything over again
+            self.capacity = (self.capacity * 2).max(self.instances.len());
+            self.buffer = Self::create_buffer(device, &self.instances, self.capacity);
+            self.dirty = None;
+            return;
+        }
+        if let Some(range) = self.dirty.take() {
+            // removes can leave the range hanging past the end
+            let end = range.end.min(self.instances.len());
+            if range.start < end {
+                let data: Vec<InstanceRaw> =
+                    self.instances[range.start..end].iter().map(Instance::to_raw).collect();
+                let offset = (range.start * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress;
+                queue.write_buffer(&self.buffer, offset, bytemuck::cast_slice(&data));
+            }
+        }
+    }
+
+    // only the part of the buffer that holds live instances
+    pub fn slice(&self) -> wgpu::BufferSlice {
+        let size = (self.instances.len().max(1) * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress;
+        self.buffer.slice(..size)
+    }
+}
@@ -1,5 +1,6 @@
 // import the deviceExtension trait so we can add buffers to our device
 
+use cgmath::Rotation3;
 use image::GenericImageView;
 use wgpu::util::DeviceExt;
 use winit::{
@@ -8,40 +9,45 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+mod animated_texture;
+mod atlas;
+mod camera;
+mod camera_controller;
+mod container;
+mod cubemap;
+mod instance;
+mod mesh;
+mod mipmap;
+mod model;
+mod pipeline;
+mod readback;
+mod recorder;
+mod scene;
+// not every pipeline uses everything build.rs generates for it
+#[allow(dead_code)]
+mod shaders;
+mod skybox;
+mod streaming;
 mod texture;
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-    //color: [f32; 3], // using texture will change to only two f32s
-    tex_coords: [f32;2]
-}
+#[cfg(test)]
+mod golden;
+
+// position, tex_coords and normal (which way the surface faces, for lighting)
+// build.rs generates it from the `#pragma vertex_buffer vertex` line and the inputs in shader.vert,
+// so the struct and the layout below can't disagree
+use shaders::ShaderVertex as Vertex;
 
-impl Vertex {
+impl mesh::MeshVertex for Vertex {
     // this expresses how the buffer actually maps data, helps pipeline know what to do with it
-    // note there is a macro that helps reduce the verbosity but this is actually nice to see how everything gets laid out
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress, // how wide a vertex is, to read ext vertex it skips this amount
-            step_mode: wgpu::InputStepMode::Vertex, // how frequently it should move between vertices
-            attributes: &[
-                // the parts of our vertex
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0, // this is what the location is we look for this data in the shader
-                    format: wgpu::VertexFormat::Float3, // corresponds to a vec3
-                },
-                // have to skip by the length of the first attribute for our offset
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float2,
-                },
-            ],
-        }
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        shaders::SHADER_VERTEX_LAYOUT
+    }
+    fn position(&self) -> [f32; 3] {
+        self.position
     }
 }
+
 /*
 // this is for when we have a color specified per vertex
 const VERTICES: &[Vertex] = &[
@@ -68,35 +74,98 @@ const VERTICES: &[Vertex] = &[
 ];
 */
 const VERTICES: &[Vertex] = &[
-    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.99240386], }, // A
-    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.56958646], }, // B
-    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.050602943], }, // C
-    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.15267089], }, // D
-    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.7347359], }, // E
+    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.99240386], normal: [0.0, 0.0, 1.0], }, // A
+    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.56958646], normal: [0.0, 0.0, 1.0], }, // B
+    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.050602943], normal: [0.0, 0.0, 1.0], }, // C
+    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.15267089], normal: [0.0, 0.0, 1.0], }, // D
+    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.7347359], normal: [0.0, 0.0, 1.0], }, // E
 ];
 
 // create a list of indices also
-const INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4];
+const INDICES: &[u32] = &[0, 1, 4, 1, 2, 4, 2, 3, 4];
+
+// an offscreen texture that we can draw into like a swapchain frame
+// it has COPY_SRC so we can pull the pixels back out
+struct OffscreenTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl OffscreenTarget {
+    // make an offscreen texture that matches the swapchain description
+    fn new(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> Self {
+        // we draw into it and then copy out of it
+        let options = texture::TextureOptions::render_target("offscreen render target", sc_desc.format);
+        // new_empty can only fail on border addressing, which render targets don't use
+        let target = texture::Texture::new_empty(device, sc_desc.width, sc_desc.height, &options).unwrap();
+        Self {
+            texture: target.texture,
+            view: target.view,
+        }
+    }
+}
+
+// this is what render() draws into, either the window or a texture nobody sees
+// the headless one is for places without a screen (CI, the build boxes without gpus)
+enum RenderTarget {
+    Window {
+        surface: wgpu::Surface,
+        swap_chain: wgpu::SwapChain,
+    },
+    Headless(OffscreenTarget),
+}
 
 struct State {
-    surface: wgpu::Surface,
+    target: RenderTarget,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    // in headless mode there is no actual swapchain but this still tells us the size and format we render with
     sc_desc: wgpu::SwapChainDescriptor,
-    swap_chain: wgpu::SwapChain,
+    // what capture() draws into in window mode, kept so recording doesn't make a new texture every frame
+    capture_target: Option<OffscreenTarget>,
 
     color: [f64; 3],
 
     size: winit::dpi::PhysicalSize<u32>,
     render_pipeline: wgpu::RenderPipeline,
 
-    vertex_buffer: wgpu::Buffer,
-    num_indices: u32,
-    index_buffer: wgpu::Buffer,
+    // everything we draw with the diffuse texture, starts out as just the pentagon
+    meshes: Vec<mesh::Mesh>,
     // now the bind group stuff for our texture
     diffuse_bind_group: wgpu::BindGroup,
+    // kept around so loaded models can make bind groups for their materials
+    texture_bind_group_layout: wgpu::BindGroupLayout,
 
     diffuse_texture: texture::Texture,
+    depth_texture: texture::Texture,
+    // the mipmap blit pipelines, every texture we load after startup goes through the same ones
+    mip_generator: mipmap::MipmapGenerator,
+
+    // the camera lives in its own bind group (set = 1 in shader.vert)
+    camera: camera::Camera,
+    camera_uniform: camera::CameraUniform,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    // kept around so the skybox can use the camera too
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    // all the ways of moving the camera, only the active one gets events
+    camera_controllers: Vec<Box<dyn camera_controller::CameraController>>,
+    active_controller: usize,
+
+    // every copy of the mesh we draw, in one vertex buffer
+    instances: instance::InstanceBuffer,
+
+    // when there is a model or a scene loaded it gets drawn instead of the pentagon
+    model: Option<model::Model>,
+    scene: Option<scene::Scene>,
+    // drawn behind everything when there is one
+    skybox: Option<skybox::Skybox>,
+    // L swaps the pentagon's texture for a plot that gets redrawn on the cpu every frame
+    live_plot: Option<(streaming::StreamingTexture, wgpu::BindGroup)>,
+    // seconds since we started, what the live plot draws
+    elapsed: f32,
+    // an animated gif or image sequence playing on the pentagon
+    animation: Option<(animated_texture::AnimatedTexture, wgpu::BindGroup)>,
 }
 
 impl State {
@@ -114,19 +183,7 @@ impl State {
             })
             .await
             .unwrap();
-        // an adapter is the reference to the gpu lets us create the device and the queeue
-        // using the adapter
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None, // they say this is the trace path?
-            )
-            .await
-            .unwrap();
+        let (device, queue) = Self::request_device(&adapter).await;
         // create the swapchain , this is the seeqeuence of buffers that get pushed t othe screen
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT, // usage is how we will use the underlyig textures, RENDER_ATTACHMENT means we draw to the screen
@@ -138,8 +195,92 @@ impl State {
         // build a swap_chain from the description
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
 
-        let diffuse_bytes = include_bytes!("../assets/tree.png");
-        let diffuse_texture = texture::Texture::from_bytes(&device,&queue,diffuse_bytes,"tree.png texture").unwrap();
+        Self::from_device(device, queue, sc_desc, RenderTarget::Window { surface, swap_chain })
+    }
+
+    // same thing as new but without a window, everything gets drawn into an offscreen texture
+    // force_fallback_adapter picks a software adapter (lavapipe/llvmpipe) so this works on machines without a gpu
+    // fails when there's no adapter to use, which is normal on a machine without a gpu or a software rasterizer
+    async fn new_headless(width: u32, height: u32, force_fallback_adapter: bool) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+        let adapter = if force_fallback_adapter {
+            // wgpu doesn't have an option for this yet so look through the adapters for a cpu one
+            instance
+                .enumerate_adapters(wgpu::BackendBit::PRIMARY)
+                .find(|adapter| adapter.get_info().device_type == wgpu::DeviceType::Cpu)
+        } else {
+            instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None, // nothing to be compatible with
+                })
+                .await
+        };
+        let adapter = match adapter {
+            Some(adapter) => adapter,
+            None if force_fallback_adapter => anyhow::bail!("there's no software adapter (like lavapipe) to render headlessly with"),
+            None => anyhow::bail!("couldn't find an adapter for headless rendering"),
+        };
+        let (device, queue) = Self::request_device(&adapter).await;
+        // there's no surface to ask for a preferred format so pick one we can read back easily
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let target = RenderTarget::Headless(OffscreenTarget::new(&device, &sc_desc));
+
+        Ok(Self::from_device(device, queue, sc_desc, target))
+    }
+
+    // tests draw with the software adapter, without one they get skipped rather than failing
+    #[cfg(test)]
+    fn for_test(width: u32, height: u32) -> Option<Self> {
+        match futures::executor::block_on(Self::new_headless(width, height, true)) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                eprintln!("skipping, {:#}", e);
+                None
+            }
+        }
+    }
+
+    // an adapter is the reference to the gpu lets us create the device and the queeue
+    // using the adapter
+    async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    // nice to haves, we only get the ones this adapter can actually do
+                    features: adapter.features() & texture::OPTIONAL_FEATURES,
+                    limits: wgpu::Limits::default(),
+                    label: None,
+                },
+                None, // they say this is the trace path?
+            )
+            .await
+            .unwrap()
+    }
+
+    // everything after this point doesn't care whether we have a window or not
+    fn from_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        sc_desc: wgpu::SwapChainDescriptor,
+        target: RenderTarget,
+    ) -> Self {
+        let size = winit::dpi::PhysicalSize::new(sc_desc.width, sc_desc.height);
+
+        // build.rs already compressed the tree into bc7 with mips, but not every gpu can read that
+        let diffuse_bytes: &[u8] = if device.features().contains(wgpu::Features::TEXTURE_COMPRESSION_BC) {
+            include_bytes!(concat!(env!("OUT_DIR"), "/assets/tree.dds"))
+        } else {
+            include_bytes!("../assets/tree.png")
+        };
+        let mip_generator = mipmap::MipmapGenerator::new(&device);
+        let diffuse_texture = texture::Texture::from_bytes(&device,&queue,&mip_generator,diffuse_bytes,"tree texture").unwrap();
 
 
         // a bind group is a way to cerate a set of resources that the shader can access
@@ -147,35 +288,10 @@ impl State {
         // its like a collection of uniforms
         // how do these bindings relate to the layout locationss that come later
         // !! they relate to setting up uniforms and uniform buffers! recall how touch designer does passing samplers to glsl materials and such 
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    // entry 0 is the sampled texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        },
-                        count: None,
-                    },
-                    // entry 1 is the sampler itself
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler {
-                            comparison: false,
-                            filtering: true,
-                        }, // they really don't explain whhat these things control
-                        count: None,
-                    },
-                ],
-                label: Some("bind group layout")
-            });
+        // the layout comes from the declarations in shader.frag, build.rs writes it into shaders.rs
+        let texture_bind_group_layout = device.create_bind_group_layout(&shaders::SHADER_SET_0);
             // apparently we can swap out bindgroups on the fly as long as the share the same descriptions
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &texture_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
@@ -190,6 +306,27 @@ impl State {
             label: Some("diffuse bind group "),
         });
 
+        // the camera goes in a uniform buffer that we rewrite whenever it changes
+        let camera = camera::Camera::new(sc_desc.width, sc_desc.height);
+        let mut camera_uniform = camera::CameraUniform::new();
+        camera_uniform.update(&camera);
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[camera_uniform]),
+            // COPY_DST so queue.write_buffer can update it
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        // the Camera block in shader.vert, see camera.glsl
+        let camera_bind_group_layout = device.create_bind_group_layout(&shaders::SHADER_SET_1);
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera bind group"),
+        });
+
         // make the shader pipeline
         // load the shader code
         let vs_src = include_str!("shader.vert");
@@ -207,84 +344,82 @@ impl State {
         let fs_data = wgpu::util::make_spirv(fs_spirv.as_binary_u8());
         */
         // attach the program as a module
-        let vs_module = device.create_shader_module(&wgpu::include_spirv!("shader.vert.spv"));
-        let fs_module = device.create_shader_module(&wgpu::include_spirv!("shader.frag.spv"));
+        let vs_module = device.create_shader_module(&shaders::SHADER_VERT.module());
+        let fs_module = device.create_shader_module(
+            &shaders::ShaderFragPermutation {
+                textured: true,
+                alpha_test: false,
+            }
+            .shader()
+            .module(),
+        );
         // make the pipeline layout
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("renedr pipeline layout"),
-                bind_group_layouts: &[&texture_bind_group_layout],
+                // the order here is the set number in the shaders
+                bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
                 push_constant_ranges: &[],
             });
+        // the mesh knows its own vertex layout, the pipeline has to use the same one
+        let pentagon = mesh::Mesh::new(&device, "pentagon", VERTICES, INDICES, 0);
+
         // make the pipeline
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("render pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                // this isn't option so not wrapped in a Some
-                module: &vs_module,
-                entry_point: "main", // this is what function will get called in the shader
-                buffers: &[Vertex::desc()], // empty because we are specifying the vertices in the vert shsader for now
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &fs_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc_desc.format,
-                    alpha_blend: wgpu::BlendState::REPLACE,
-                    color_blend: wgpu::BlendState::REPLACE,
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                // decide whether triangle faces forward with counter clock wise
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::Back, // triangles not facing froward get removed
-                // have to mess with features if you don't want this
-                polygon_mode: wgpu::PolygonMode::Fill,
-            },
-            depth_stencil: None,
-            // not explained in great detail but has to do with multisampling
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0, // means use all samples
-                // antialiasing setting
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let render_pipeline = pipeline::create_render_pipeline(
+            &device,
+            "render pipeline",
+            &render_pipeline_layout,
+            sc_desc.format,
+            Some(pipeline::DepthSettings::OPAQUE),
+            // slot 0 is per vertex, slot 1 is per instance
+            &[pentagon.vertex_layout.clone(), shaders::SHADER_INSTANCE_LAYOUT],
+            &vs_module,
+            &fs_module,
+        );
 
-        // setup the vertex buffer
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            // contents need to be a &[u8] this converts our structs
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsage::VERTEX,
-        });
+        // start with a single copy sitting at the origin, more get added with the + key
+        let instances = instance::InstanceBuffer::new(
+            &device,
+            vec![instance::Instance::at(cgmath::Vector3::new(0.0, 0.0, 0.0))],
+        );
 
-        // setup the index buffer
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsage::INDEX,
-        });
+        // the depth buffer has to match the size of what we draw into
+        let depth_texture = texture::Texture::create_depth_texture(&device, &sc_desc, "depth texture");
 
         // return a Self
         Self {
-            surface,
+            target,
             device,
             queue,
             sc_desc,
-            swap_chain,
+            capture_target: None,
             size,
             color: [0.0; 3],
             render_pipeline,
-            num_indices: INDICES.len() as u32,
-            vertex_buffer,
-            index_buffer,
+            meshes: vec![pentagon],
             diffuse_bind_group,
-            diffuse_texture
+            diffuse_texture,
+            depth_texture,
+            mip_generator,
+            camera,
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+            camera_bind_group_layout,
+            camera_controllers: vec![
+                Box::new(camera_controller::OrbitController::new(0.005)),
+                Box::new(camera_controller::FlyController::new(2.0, 0.003)),
+                Box::new(camera_controller::PanZoomController::new(0.002)),
+            ],
+            active_controller: 0,
+            instances,
+            model: None,
+            scene: None,
+            skybox: None,
+            live_plot: None,
+            elapsed: 0.0,
+            animation: None,
+            texture_bind_group_layout,
         }
     }
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -292,8 +427,21 @@ impl State {
         self.size = new_size;
         self.sc_desc.width = new_size.width;
         self.sc_desc.height = new_size.height;
-        // remake the swapchain
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        // the depth buffer has to stay the same size as the color target
+        self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.sc_desc, "depth texture");
+        // the next capture makes one the new size
+        self.capture_target = None;
+        // keep the projection matching the new shape of the window
+        self.camera.resize(new_size.width, new_size.height);
+        // remake the swapchain, or the offscreen texture if that's what we draw into
+        match &mut self.target {
+            RenderTarget::Window { surface, swap_chain } => {
+                *swap_chain = self.device.create_swap_chain(surface, &self.sc_desc);
+            }
+            RenderTarget::Headless(offscreen) => {
+                *offscreen = OffscreenTarget::new(&self.device, &self.sc_desc);
+            }
+        }
     }
     // no capture info yet
     fn input(&mut self, event: &WindowEvent) -> bool {
@@ -308,13 +456,311 @@ impl State {
                 ];
                 true
             }
-            _ => false,
+            // O flips between perspective and orthographic
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::O),
+                        ..
+                    },
+                ..
+            } => {
+                self.camera.toggle_projection();
+                true
+            }
+            // C moves on to the next camera controller
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::C),
+                        ..
+                    },
+                ..
+            } => {
+                self.active_controller = (self.active_controller + 1) % self.camera_controllers.len();
+                log::info!("camera controller: {}", self.camera_controllers[self.active_controller].name());
+                true
+            }
+            // + adds another copy of the mesh, - takes the newest one away
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Equals),
+                        ..
+                    },
+                ..
+            } => {
+                self.add_instance();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Minus),
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(&id) = self.instances.ids().last() {
+                    self.instances.remove(id);
+                }
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::L),
+                        ..
+                    },
+                ..
+            } => {
+                if let Err(e) = self.toggle_live_plot() {
+                    eprintln!("couldn't make the live plot {:?}", e);
+                }
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } if self.animation_input(*key) => true,
+            // anything else might be for the camera
+            _ => self.camera_controllers[self.active_controller].process_event(event),
+        }
+    }
+    // load an .obj file to draw instead of the pentagon
+    fn load_model<P: AsRef<std::path::Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        let model = model::Model::load(
+            &self.device,
+            &self.queue,
+            &self.mip_generator,
+            &self.texture_bind_group_layout,
+            path,
+        )?;
+        if let Some(bounds) = model.bounds() {
+            self.frame_bounds(bounds);
+        }
+        self.model = Some(model);
+        Ok(())
+    }
+    // load a .gltf/.glb scene, it brings its own transforms so the instance grid isn't used for it
+    fn load_scene<P: AsRef<std::path::Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        let scene = scene::Scene::load(
+            &self.device,
+            &self.queue,
+            &self.mip_generator,
+            &self.texture_bind_group_layout,
+            path,
+        )?;
+        if let Some(bounds) = scene.bounds()? {
+            self.frame_bounds(bounds);
+        }
+        self.scene = Some(scene);
+        Ok(())
+    }
+    // a folder of six faces (px.png, nx.png, ...) or an equirectangular .hdr/.exr/image
+    fn load_skybox<P: AsRef<std::path::Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let texture = if path.is_dir() {
+            cubemap::from_folder(&self.device, &self.queue, path, "skybox")?
+        } else {
+            let bytes = std::fs::read(path)?;
+            cubemap::from_equirect(&self.device, &self.queue, &self.mip_generator, &bytes, "skybox")?
+        };
+        self.skybox = Some(skybox::Skybox::new(
+            &self.device,
+            self.sc_desc.format,
+            &self.camera_bind_group_layout,
+            texture,
+        ));
+        Ok(())
+    }
+    // a set = 0 bind group for drawing with this texture
+    fn texture_bind_group(&self, texture: &texture::Texture, label: &str) -> wgpu::BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some(label),
+        })
+    }
+    fn toggle_live_plot(&mut self) -> anyhow::Result<()> {
+        if self.live_plot.take().is_none() {
+            let options = texture::TextureOptions::new("live plot");
+            let live_texture = streaming::StreamingTexture::new(&self.device, 256, 256, &options)?;
+            let bind_group = self.texture_bind_group(&live_texture.texture, "live plot bind group");
+            self.live_plot = Some((live_texture, bind_group));
+        }
+        Ok(())
+    }
+    // scroll the plot one pixel left and draw a new column of a couple of waves on the right
+    fn update_live_plot(&mut self) -> anyhow::Result<()> {
+        let (live_texture, _) = match &mut self.live_plot {
+            Some(live_plot) => live_plot,
+            None => return Ok(()),
+        };
+        let (width, height) = (live_texture.texture.size.width as usize, live_texture.texture.size.height as usize);
+        let t = self.elapsed;
+        let waves = [
+            ((t * 2.0).sin(), [255, 80, 80, 255]),
+            ((t * 3.1).cos() * 0.6, [80, 255, 120, 255]),
+        ];
+        let pixels = live_texture.pixels_mut();
+        for y in 0..height {
+            let row = &mut pixels[y * width * 4..(y + 1) * width * 4];
+            row.copy_within(4.., 0);
+            // -1 at the bottom, 1 at the top
+            let value = 1.0 - 2.0 * y as f32 / height as f32;
+            let mut color = [20, 20, 30, 255];
+            for (wave, wave_color) in waves.iter() {
+                if (wave - value).abs() < 2.0 / height as f32 {
+                    color = *wave_color;
+                }
+            }
+            row[(width - 1) * 4..].copy_from_slice(&color);
+        }
+        live_texture.upload(&self.queue)
+    }
+    // a .gif file, or a folder of numbered images played at fps
+    fn load_animation<P: AsRef<std::path::Path>>(&mut self, path: P, fps: f32) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let animation = if path.is_dir() {
+            animated_texture::AnimatedTexture::from_sequence(&self.device, &self.queue, path, fps, "animation")?
+        } else {
+            let bytes = std::fs::read(path)?;
+            animated_texture::AnimatedTexture::from_gif(&self.device, &self.queue, &bytes, "animation")?
+        };
+        let bind_group = self.texture_bind_group(&animation.texture, "animation bind group");
+        self.animation = Some((animation, bind_group));
+        Ok(())
+    }
+    // P pauses, [ and ] change the speed, \ turns looping on and off
+    fn animation_input(&mut self, key: VirtualKeyCode) -> bool {
+        let animation = match &mut self.animation {
+            Some((animation, _)) => animation,
+            None => return false,
+        };
+        match key {
+            VirtualKeyCode::P => animation.toggle_playing(),
+            VirtualKeyCode::LBracket => animation.set_speed(animation.speed() / 2.0),
+            VirtualKeyCode::RBracket => animation.set_speed(animation.speed() * 2.0),
+            VirtualKeyCode::Backslash => animation.looping = !animation.looping,
+            _ => return false,
         }
+        true
+    }
+    // every image in the folder packed into one atlas, drawn as one instance each in a single draw call
+    fn load_sprites<P: AsRef<std::path::Path>>(&mut self, folder: P) -> anyhow::Result<()> {
+        let mut paths: Vec<_> = std::fs::read_dir(folder)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| image::ImageFormat::from_path(path).is_ok())
+            .collect();
+        paths.sort();
+        // 8192 is the biggest texture every gpu is guaranteed to handle
+        let mut builder = atlas::AtlasBuilder::new(8192);
+        for path in &paths {
+            builder.add(&image::open(path)?);
+        }
+        let atlas = builder.build(&self.device, &self.queue, &texture::TextureOptions::new("sprite atlas"))?;
+
+        self.diffuse_bind_group = self.texture_bind_group(&atlas.texture, "sprite atlas bind group");
+        self.diffuse_texture = atlas.texture;
+
+        // a square grid of sprites facing the camera
+        let per_row = (atlas.rects.len() as f32).sqrt().ceil().max(1.0) as usize;
+        let instances = atlas
+            .rects
+            .iter()
+            .enumerate()
+            .map(|(i, rect)| {
+                let (column, row) = ((i % per_row) as f32, (i / per_row) as f32);
+                let mut instance = instance::Instance::at(cgmath::Vector3::new(
+                    column - per_row as f32 / 2.0,
+                    per_row as f32 / 2.0 - row,
+                    0.0,
+                ));
+                instance.uv_rect = rect.offset_scale();
+                instance
+            })
+            .collect();
+        self.instances = instance::InstanceBuffer::new(&self.device, instances);
+        Ok(())
+    }
+    // point the camera at the middle of the box and back off far enough to see all of it
+    fn frame_bounds(&mut self, bounds: mesh::Aabb) {
+        use cgmath::InnerSpace;
+        let center = bounds.center();
+        let radius = (bounds.max - bounds.min).magnitude() / 2.0;
+        self.camera.target = center;
+        self.camera.eye = center + cgmath::Vector3::new(0.0, 0.0, radius.max(0.1) * 2.5);
+    }
+    // lays the copies out in a grid behind the first one, each a slightly different tint
+    fn add_instance(&mut self) {
+        const PER_ROW: usize = 10;
+        let n = self.instances.len();
+        let (column, row) = ((n % PER_ROW) as f32, (n / PER_ROW) as f32);
+        let mut instance = instance::Instance::at(cgmath::Vector3::new(
+            column - PER_ROW as f32 / 2.0,
+            0.0,
+            -1.0 - row,
+        ));
+        instance.rotation = cgmath::Quaternion::from_angle_y(cgmath::Deg(n as f32 * 10.0));
+        instance.tint = [1.0 - column / PER_ROW as f32, 1.0, 0.5 + column / (2.0 * PER_ROW as f32), 1.0];
+        self.instances.add(instance);
+    }
+    // mouse movement comes in as a device event rather than a window event
+    fn mouse_motion(&mut self, dx: f64, dy: f64) {
+        self.camera_controllers[self.active_controller].process_mouse_motion(dx, dy);
+    }
+    // dt is how long the last frame took so movement doesn't depend on the frame rate
+    fn update(&mut self, dt: std::time::Duration) {
+        self.elapsed += dt.as_secs_f32();
+        if let Err(e) = self.update_live_plot() {
+            eprintln!("couldn't update the live plot {:?}", e);
+        }
+        if let Some((animation, _)) = &mut self.animation {
+            if let Err(e) = animation.update(&self.queue, dt) {
+                eprintln!("couldn't update the animation {:?}", e);
+            }
+        }
+        self.camera_controllers[self.active_controller].update_camera(&mut self.camera, dt);
+        // only the instances that changed get sent over
+        self.instances.upload(&self.device, &self.queue);
+        // push the latest camera matrices to the gpu
+        self.camera_uniform.update(&self.camera);
+        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
     }
-    fn update(&mut self) {}
     fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
-        // get a frame to render to
-        let frame = self.swap_chain.get_current_frame()?.output;
+        match &self.target {
+            RenderTarget::Window { swap_chain, .. } => {
+                // get a frame to render to
+                let frame = swap_chain.get_current_frame()?.output;
+                self.render_to(&frame.view);
+            }
+            RenderTarget::Headless(offscreen) => self.render_to(&offscreen.view),
+        }
+        Ok(())
+    }
+
+    // draws one frame into whatever view we are handed, this is shared by the window and headless targets
+    fn render_to(&self, view: &wgpu::TextureView) {
         // make a command encoder for sending commands to the gpu
         let mut encoder = self
             .device
@@ -331,7 +777,7 @@ impl State {
                 label: Some("Render pass"),
                 // describes where we are going to draw our color
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
+                    attachment: view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -343,7 +789,15 @@ impl State {
                         store: true,
                     },
                 }],
-                depth_stencil_attachment: None, // what is a depth stencil?
+                // clear to 1.0, the far plane, so anything we draw is closer
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
             });
 
             // do things with the pipeline
@@ -351,40 +805,165 @@ impl State {
 
             // set the bind group
             // the first argument associates with the first number in our layout(set=0, binding = 0 or 1) uniform texture for our fragment
-            render_pass.set_bind_group(0,&self.diffuse_bind_group,&[]);
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            // the instance transforms go in the second slot
+            render_pass.set_vertex_buffer(1, self.instances.slice());
+            let instances = 0..self.instances.len() as u32;
 
-            // set the vertex buffer, what slot to use for this buffer.
-            // interesting! so how do the locations compare to the slots?
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            if let Some(scene) = &self.scene {
+                use scene::DrawScene;
+                render_pass.draw_scene(scene);
+            } else if let Some(model) = &self.model {
+                // each mesh sets its own vertex/index buffers and material
+                use model::DrawModel;
+                render_pass.draw_model_instanced(model, instances);
+            } else {
+                match (&self.live_plot, &self.animation) {
+                    (Some((_, bind_group)), _) | (None, Some((_, bind_group))) => {
+                        render_pass.set_bind_group(0, bind_group, &[])
+                    }
+                    (None, None) => render_pass.set_bind_group(0,&self.diffuse_bind_group,&[]),
+                }
 
-            // use the pipeline to draw stuff, first is number of vertices and the second is instance count
-            // this is for when you don't use index arrays
-            //render_pass.draw(0..self.num_vertices as u32, 0..1);
+                // each mesh sets its vertex buffer in slot 0 and its index buffer with whatever format it picked
+                // the last range is which instances to draw, so this draws all of them in one go
+                use mesh::DrawMesh;
+                for mesh in &self.meshes {
+                    render_pass.draw_mesh_instanced(mesh, instances.clone());
+                }
+            }
 
-            // what are each of these arguments?
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            // last so the depth test throws out every sky pixel that's behind something
+            if let Some(skybox) = &self.skybox {
+                use skybox::DrawSkybox;
+                render_pass.draw_skybox(skybox, &self.camera_bind_group);
+            }
         }
         // pass anything that implements iter for our queue
         self.queue.submit(std::iter::once(encoder.finish()));
+    }
 
-        Ok(())
+    // render a frame and read it back as an image, for screenshots and tests
+    // in window mode the swapchain frames can't be copied from so this draws into capture_target instead
+    fn capture(&mut self) -> anyhow::Result<image::RgbaImage> {
+        if let RenderTarget::Window { .. } = self.target {
+            if self.capture_target.is_none() {
+                self.capture_target = Some(OffscreenTarget::new(&self.device, &self.sc_desc));
+            }
+        }
+        let offscreen = match &self.target {
+            RenderTarget::Headless(offscreen) => offscreen,
+            RenderTarget::Window { .. } => self.capture_target.as_ref().unwrap(),
+        };
+        self.render_to(&offscreen.view);
+        readback::read_texture(
+            &self.device,
+            &self.queue,
+            &offscreen.texture,
+            self.sc_desc.format,
+            self.sc_desc.width,
+            self.sc_desc.height,
+        )
+    }
+}
+
+// the value after a command line flag, like the path in `--model cube.obj`
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    // another flag straight after means this one didn't get a value
+    args.get(i + 1).map(String::as_str).filter(|value| !value.starts_with("--"))
+}
+
+// `--model path.obj` or `--scene path.gltf` draws that instead of the pentagon
+// `--sprites folder` swaps the pentagon's texture for an atlas of every image in the folder
+// `--animated path.gif` (or a folder of numbered frames, with `--fps 24`) plays on the pentagon
+// `--skybox path` puts a sky behind it, see State::load_skybox
+fn load_from_args(state: &mut State, args: &[String]) -> anyhow::Result<()> {
+    if let Some(model_path) = arg_value(args, "--model") {
+        state.load_model(model_path)?;
+    }
+    if let Some(scene_path) = arg_value(args, "--scene") {
+        state.load_scene(scene_path)?;
+    }
+    if let Some(sprites_path) = arg_value(args, "--sprites") {
+        state.load_sprites(sprites_path)?;
+    }
+    if let Some(animation_path) = arg_value(args, "--animated") {
+        let fps = arg_value(args, "--fps").map_or(Ok(24.0), str::parse)?;
+        state.load_animation(animation_path, fps)?;
+    }
+    if let Some(skybox_path) = arg_value(args, "--skybox") {
+        state.load_skybox(skybox_path)?;
     }
+    Ok(())
+}
+
+// `--record out.y4m` (or a folder for numbered pngs) records from the first frame
+// `--record-fps 30` is the simulated frame rate, `--record-frames 120` stops after that many
+// without --record-frames it stops after default_frames, or goes on till it's stopped if that's None
+fn recorder_from_args(args: &[String], default_frames: Option<u32>) -> anyhow::Result<Option<recorder::Recorder>> {
+    let path = match arg_value(args, "--record") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let fps = arg_value(args, "--record-fps").map_or(Ok(30), str::parse)?;
+    let max_frames = match arg_value(args, "--record-frames") {
+        Some(frames) => Some(frames.parse()?),
+        None => default_frames,
+    };
+    Ok(Some(recorder::Recorder::new(path, fps, max_frames)?))
+}
+
+// step the simulation by exactly one recorded frame and write what it looks like
+fn record_frame(state: &mut State, recorder: &mut recorder::Recorder) -> anyhow::Result<()> {
+    state.update(recorder.frame_time());
+    let frame = state.capture()?;
+    recorder.write_frame(&frame)
 }
 
 fn main() {
     env_logger::init();
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
 
     // no idea about this line with the futures
     use futures::executor::block_on;
 
+    // `--headless out.png` renders a single frame without opening a window
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--headless") {
+        let path = arg_value(&args, "--headless").unwrap_or("headless.png");
+        let (width, height) = (800, 600);
+        let force_fallback_adapter = args.iter().any(|arg| arg == "--fallback-adapter");
+        let mut state = block_on(State::new_headless(width, height, force_fallback_adapter)).unwrap();
+        load_from_args(&mut state, &args).unwrap();
+        // `--headless --record out.y4m` renders a whole clip instead, 120 frames unless it's told otherwise
+        if let Some(mut recorder) = recorder_from_args(&args, Some(120)).unwrap() {
+            while !recorder.is_done() {
+                record_frame(&mut state, &mut recorder).unwrap();
+            }
+            recorder.finish().unwrap();
+            return;
+        }
+        let frame = state.capture().unwrap();
+        readback::save_image(&frame, path).unwrap();
+        return;
+    }
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
+
     // apparentnly this takes something async and blocks till we've got it
     let mut state: State = block_on(State::new(&window));
+    load_from_args(&mut state, &args).unwrap();
+    let mut screenshot_count = 0;
+    let mut recording_count = 0;
+    let mut recorder = recorder_from_args(&args, None).unwrap();
+    let mut last_render_time = std::time::Instant::now();
 
     event_loop.run(move |event, _, control_flow| match event {
+        Event::DeviceEvent {
+            event: DeviceEvent::MouseMotion { delta },
+            ..
+        } => state.mouse_motion(delta.0, delta.1),
         Event::WindowEvent {
             ref event,
             window_id,
@@ -398,6 +977,44 @@ fn main() {
                             virtual_keycode: Some(VirtualKeyCode::Escape),
                             ..
                         } => *control_flow = ControlFlow::Exit,
+                        // F12 saves whatever is on screen
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            ..
+                        } => {
+                            screenshot_count += 1;
+                            let path = format!("screenshot-{}.png", screenshot_count);
+                            match state.capture().and_then(|frame| readback::save_image(&frame, &path)) {
+                                Ok(_) => println!("saved {}", path),
+                                Err(e) => eprintln!("couldn't save screenshot {:?}", e),
+                            }
+                        }
+                        // F10 starts and stops recording to recording-N.y4m
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F10),
+                            ..
+                        } => match recorder.take() {
+                            Some(finished) => {
+                                let frames = finished.frames_written();
+                                match finished.finish() {
+                                    Ok(_) => println!("stopped recording after {} frames", frames),
+                                    Err(e) => eprintln!("couldn't finish recording {:?}", e),
+                                }
+                            }
+                            None => {
+                                recording_count += 1;
+                                let path = format!("recording-{}.y4m", recording_count);
+                                match recorder::Recorder::new(&path, 30, None) {
+                                    Ok(started) => {
+                                        println!("recording to {}", path);
+                                        recorder = Some(started);
+                                    }
+                                    Err(e) => eprintln!("couldn't start recording {:?}", e),
+                                }
+                            }
+                        },
                         _ => {}
                     },
                     // size change events
@@ -412,8 +1029,25 @@ fn main() {
             }
         }
         Event::RedrawRequested(_) => {
-            // use the update on our state
-            state.update();
+            let now = std::time::Instant::now();
+            let dt = now - last_render_time;
+            last_render_time = now;
+            // while recording the clock moves one recorded frame at a time, however long that takes for real
+            if let Some(active) = &mut recorder {
+                if let Err(e) = record_frame(&mut state, active) {
+                    eprintln!("recording stopped {:?}", e);
+                    recorder = None;
+                } else if active.is_done() {
+                    let finished = recorder.take().unwrap();
+                    println!("recorded {} frames", finished.frames_written());
+                    if let Err(e) = finished.finish() {
+                        eprintln!("couldn't finish recording {:?}", e);
+                    }
+                }
+            } else {
+                // use the update on our state
+                state.update(dt);
+            }
             // then use render
             match state.render() {
                 Ok(_) => {} // nothing bad happened, we are fine
@@ -0,0 +1,135 @@
+// a mesh owns its vertex and index buffers and knows how to draw itself
+// everything that makes geometry (the pentagon, obj models, gltf primitives) ends up as one of these
+use cgmath::Point3;
+use std::ops::Range;
+use wgpu::util::DeviceExt;
+
+// anything we can put in a vertex buffer, it needs a layout for the pipeline and a position for the bounding box
+pub trait MeshVertex: bytemuck::Pod {
+    fn desc() -> wgpu::VertexBufferLayout<'static>;
+    fn position(&self) -> [f32; 3];
+}
+
+// axis aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    // None if there are no points to wrap
+    pub fn from_points<I: IntoIterator<Item = [f32; 3]>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = Point3::from(points.next()?);
+        Some(points.fold(Self { min: first, max: first }, |aabb, [x, y, z]| Self {
+            min: Point3::new(aabb.min.x.min(x), aabb.min.y.min(y), aabb.min.z.min(z)),
+            max: Point3::new(aabb.max.x.max(x), aabb.max.y.max(y), aabb.max.z.max(z)),
+        }))
+    }
+
+    // the smallest box holding both
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        Point3::new(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+}
+
+pub struct Mesh {
+    pub name: String,
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    // Uint16 when every index fits, it's half the memory
+    pub index_format: wgpu::IndexFormat,
+    pub num_indices: u32,
+    // the layout the vertex buffer was written with, the pipeline drawing this has to use the same one
+    pub vertex_layout: wgpu::VertexBufferLayout<'static>,
+    pub bounds: Aabb,
+    // index into whatever list of materials goes with this mesh (Model::materials, Scene::materials)
+    // meshes drawn on their own like the pentagon bind their texture themselves and ignore it
+    pub material: usize,
+}
+
+impl Mesh {
+    pub fn new<V: MeshVertex>(
+        device: &wgpu::Device,
+        name: &str,
+        vertices: &[V],
+        indices: &[u32],
+        material: usize,
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", name)),
+            // contents need to be a &[u8] this converts our structs
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        // if there are few enough vertices every index fits in a u16
+        let (index_format, index_buffer) = if vertices.len() <= u16::MAX as usize {
+            let indices: Vec<u16> = indices.iter().map(|&i| i as u16).collect();
+            (wgpu::IndexFormat::Uint16, Self::create_index_buffer(device, name, bytemuck::cast_slice(&indices)))
+        } else {
+            (wgpu::IndexFormat::Uint32, Self::create_index_buffer(device, name, bytemuck::cast_slice(indices)))
+        };
+
+        let bounds = Aabb::from_points(vertices.iter().map(MeshVertex::position)).unwrap_or(Aabb {
+            min: Point3::new(0.0, 0.0, 0.0),
+            max: Point3::new(0.0, 0.0, 0.0),
+        });
+
+        Self {
+            name: name.to_string(),
+            vertex_buffer,
+            index_buffer,
+            index_format,
+            num_indices: indices.len() as u32,
+            vertex_layout: V::desc(),
+            bounds,
+            material,
+        }
+    }
+
+    fn create_index_buffer(device: &wgpu::Device, name: &str, contents: &[u8]) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", name)),
+            contents,
+            usage: wgpu::BufferUsage::INDEX,
+        })
+    }
+}
+
+// lets a render pass draw a mesh directly
+// the pipeline, bind groups and instance buffer (slot 1) need to be set already
+pub trait DrawMesh<'a> {
+    fn draw_mesh_instanced(&mut self, mesh: &'a Mesh, instances: Range<u32>);
+}
+
+impl<'a, 'b> DrawMesh<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_mesh_instanced(&mut self, mesh: &'b Mesh, instances: Range<u32>) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+        self.draw_indexed(0..mesh.num_indices, 0, instances);
+    }
+}
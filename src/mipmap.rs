@@ -0,0 +1,133 @@
+// filling in the smaller mip levels of a texture
+// each level gets drawn from the one above it with a fullscreen triangle (blit.vert/blit.frag)
+use crate::shaders;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// how many levels it takes to get from width x height down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+// the blit pass needs to draw into the texture and read it back as floats
+pub fn can_generate_on_gpu(format: wgpu::TextureFormat) -> bool {
+    let info = format.describe();
+    let float = matches!(info.sample_type, wgpu::TextureSampleType::Float { .. });
+    float
+        && info
+            .guaranteed_format_features
+            .allowed_usages
+            .contains(wgpu::TextureUsage::RENDER_ATTACHMENT)
+}
+
+// the blit pipeline and everything it needs, made once and kept in State
+// the pipeline depends on the format it draws into so those get made the first time a format shows up
+pub struct MipmapGenerator {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    vs_module: wgpu::ShaderModule,
+    fs_module: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+    pipelines: RefCell<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        // blit.frag's `#pragma unfilterable` makes this one take float formats that can't be filtered too
+        let bind_group_layout = device.create_bind_group_layout(&shaders::BLIT_SET_0);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("mipmap pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let vs_module = device.create_shader_module(&shaders::BLIT_VERT.module());
+        let fs_module = device.create_shader_module(&shaders::BLIT_FRAG.module());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mipmap sampler"),
+            ..Default::default()
+        });
+        Self {
+            bind_group_layout,
+            pipeline_layout,
+            vs_module,
+            fs_module,
+            sampler,
+            pipelines: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // the texture has to have been made with SAMPLED | RENDER_ATTACHMENT and level 0 already written
+    pub fn generate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        mip_count: u32,
+    ) {
+        let mut pipelines = self.pipelines.borrow_mut();
+        // no vertex buffers, the triangle comes out of gl_VertexIndex
+        let pipeline = pipelines.entry(format).or_insert_with(|| {
+            crate::pipeline::create_render_pipeline(
+                device,
+                "mipmap pipeline",
+                &self.pipeline_layout,
+                format,
+                None,
+                &[],
+                &self.vs_module,
+                &self.fs_module,
+            )
+        });
+
+        // a view for every level so we can read one and draw into the next
+        let views: Vec<wgpu::TextureView> = (0..mip_count)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("mip level"),
+                    base_mip_level: level,
+                    level_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mipmap encoder"),
+        });
+        // one pass per level, each reading the level that was just written
+        for target in 1..mip_count as usize {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&views[target - 1]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+                label: Some("mipmap bind group"),
+            });
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("mipmap pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: &views[target],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // every pixel gets drawn so there's nothing worth keeping
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
@@ -0,0 +1,213 @@
+// loading .obj files (and the .mtl files that come with them) into something we can draw
+use crate::mesh::{Aabb, DrawMesh, Mesh};
+use crate::mipmap::MipmapGenerator;
+use crate::{texture, Vertex};
+use anyhow::*;
+use std::ops::Range;
+use std::path::Path;
+
+pub struct Material {
+    pub name: String,
+    pub diffuse_texture: texture::Texture,
+    // same layout as the pentagon's diffuse bind group (set = 0)
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        diffuse_texture: texture::Texture,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+            ],
+            label: Some(name),
+        });
+        Self {
+            name: name.to_string(),
+            diffuse_texture,
+            bind_group,
+        }
+    }
+
+    // for materials without a texture, a 1x1 texture of the diffuse color does the same job
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mip_generator: &MipmapGenerator,
+        name: &str,
+        color: [f32; 3],
+        layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let [r, g, b] = color;
+        let to_byte = |c: f32| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            1,
+            1,
+            image::Rgba([to_byte(r), to_byte(g), to_byte(b), 255]),
+        ));
+        let diffuse_texture = texture::Texture::from_image(device, queue, mip_generator, &img, name)?;
+        Ok(Self::new(device, name, diffuse_texture, layout))
+    }
+}
+
+pub struct Model {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+}
+
+impl Model {
+    // texture paths in the .mtl are relative to the .obj file
+    pub fn load<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mip_generator: &MipmapGenerator,
+        layout: &wgpu::BindGroupLayout,
+        path: P,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let (obj_models, obj_materials) = tobj::load_obj(
+            path,
+            &tobj::LoadOptions {
+                // one index per vertex instead of separate ones for position/normal/uv, that's what the gpu wants
+                single_index: true,
+                triangulate: true,
+                ..Default::default()
+            },
+        )
+        .with_context(|| format!("couldn't load {}", path.display()))?;
+        // the geometry is still worth drawing without its .mtl, everything just gets the default material
+        let obj_materials = obj_materials.unwrap_or_else(|e| {
+            log::warn!("couldn't load materials for {}: {}", path.display(), e);
+            Vec::new()
+        });
+        let containing_folder = path.parent().context("model path has no parent directory")?;
+
+        let mut materials = Vec::new();
+        for mat in obj_materials {
+            let material = if mat.diffuse_texture.is_empty() {
+                Material::from_color(device, queue, mip_generator, &mat.name, mat.diffuse, layout)?
+            } else {
+                let texture_path = containing_folder.join(&mat.diffuse_texture);
+                let bytes = std::fs::read(&texture_path)
+                    .with_context(|| format!("couldn't read texture {}", texture_path.display()))?;
+                // obj uvs often run past 0..1 expecting the texture to tile
+                // models get looked at from every angle, anisotropy keeps floors and walls sharp
+                let options = texture::TextureOptions::new(&mat.diffuse_texture).repeat().anisotropy(16);
+                let diffuse_texture = texture::Texture::from_bytes_with_options(device, queue, mip_generator, &bytes, &options)?;
+                Material::new(device, &mat.name, diffuse_texture, layout)
+            };
+            materials.push(material);
+        }
+        // meshes that don't ask for a material (or ask for one that didn't load) get a plain white one
+        let default_material = materials.len();
+        materials.push(Material::from_color(device, queue, mip_generator, "default material", [1.0; 3], layout)?);
+
+        let meshes = obj_models
+            .into_iter()
+            .map(|m| {
+                let vertices = mesh_vertices(&m.mesh);
+                let material = m.mesh.material_id.filter(|&id| id < default_material).unwrap_or(default_material);
+                Mesh::new(device, &m.name, &vertices, &m.mesh.indices, material)
+            })
+            .collect();
+
+        Ok(Self { meshes, materials })
+    }
+
+    // a box around every mesh in the model
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.meshes
+            .iter()
+            .map(|mesh| mesh.bounds)
+            .fold(None, |total: Option<Aabb>, bounds| {
+                Some(total.map_or(bounds, |total| total.union(&bounds)))
+            })
+    }
+}
+
+// turn tobj's flat float arrays into our Vertex
+fn mesh_vertices(mesh: &tobj::Mesh) -> Vec<Vertex> {
+    let vertex_count = mesh.positions.len() / 3;
+    let mut vertices: Vec<Vertex> = (0..vertex_count)
+        .map(|i| Vertex {
+            position: [
+                mesh.positions[i * 3],
+                mesh.positions[i * 3 + 1],
+                mesh.positions[i * 3 + 2],
+            ],
+            // obj has v going up, textures have it going down
+            tex_coords: if mesh.texcoords.is_empty() {
+                [0.0, 0.0]
+            } else {
+                [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+            },
+            normal: if mesh.normals.is_empty() {
+                [0.0, 0.0, 0.0]
+            } else {
+                [
+                    mesh.normals[i * 3],
+                    mesh.normals[i * 3 + 1],
+                    mesh.normals[i * 3 + 2],
+                ]
+            },
+        })
+        .collect();
+
+    // no normals in the file, add up the face normals around each vertex instead
+    if mesh.normals.is_empty() {
+        for triangle in mesh.indices.chunks(3) {
+            let corner = |i: u32| {
+                let p = vertices[i as usize].position;
+                cgmath::Vector3::new(p[0], p[1], p[2])
+            };
+            let (a, b, c) = (corner(triangle[0]), corner(triangle[1]), corner(triangle[2]));
+            let face_normal = (b - a).cross(c - a);
+            for &i in triangle {
+                let normal = &mut vertices[i as usize].normal;
+                normal[0] += face_normal.x;
+                normal[1] += face_normal.y;
+                normal[2] += face_normal.z;
+            }
+        }
+        for vertex in vertices.iter_mut() {
+            let [x, y, z] = vertex.normal;
+            let length = (x * x + y * y + z * z).sqrt();
+            if length > 0.0 {
+                vertex.normal = [x / length, y / length, z / length];
+            }
+        }
+    }
+    vertices
+}
+
+// lets a render pass draw models directly
+// the pipeline, camera bind group and instance buffer need to be set already
+pub trait DrawModel<'a> {
+    fn draw_model_instanced(&mut self, model: &'a Model, instances: Range<u32>);
+}
+
+impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    // one draw call for each mesh, with whatever material it uses
+    fn draw_model_instanced(&mut self, model: &'b Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            let material = &model.materials[mesh.material];
+            self.set_bind_group(0, &material.bind_group, &[]);
+            self.draw_mesh_instanced(mesh, instances.clone());
+        }
+    }
+}
@@ -0,0 +1,85 @@
+// the render pipeline setup is mostly the same every time, this keeps the boilerplate in one place
+use crate::texture;
+
+// how the depth test behaves for a pipeline
+#[derive(Copy, Clone, Debug)]
+pub struct DepthSettings {
+    // a fragment is kept when compare(fragment depth, stored depth) is true
+    pub compare: wgpu::CompareFunction,
+    // whether surviving fragments overwrite the stored depth
+    pub write_enabled: bool,
+}
+
+impl DepthSettings {
+    // regular solid geometry, the closest thing wins
+    pub const OPAQUE: Self = Self {
+        compare: wgpu::CompareFunction::Less,
+        write_enabled: true,
+    };
+    // still hidden behind things but doesn't hide anything itself, for transparent stuff or backgrounds
+    pub const READ_ONLY: Self = Self {
+        compare: wgpu::CompareFunction::LessEqual,
+        write_enabled: false,
+    };
+}
+
+// depth: None makes a pipeline that can be used in a pass without a depth attachment
+#[allow(clippy::too_many_arguments)]
+pub fn create_render_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    depth: Option<DepthSettings>,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    vs_module: &wgpu::ShaderModule,
+    fs_module: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            // this isn't option so not wrapped in a Some
+            module: vs_module,
+            entry_point: "main", // this is what function will get called in the shader
+            buffers: vertex_layouts,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: fs_module,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format: color_format,
+                alpha_blend: wgpu::BlendState::REPLACE,
+                color_blend: wgpu::BlendState::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            // decide whether triangle faces forward with counter clock wise
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::Back, // triangles not facing froward get removed
+            // have to mess with features if you don't want this
+            polygon_mode: wgpu::PolygonMode::Fill,
+        },
+        // the depth stencil is a texture that remembers how far away the closest thing drawn at each pixel is
+        // so things behind it get thrown out no matter what order we draw in
+        depth_stencil: depth.map(|depth| wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: depth.write_enabled,
+            depth_compare: depth.compare,
+            // we don't use stencils yet
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+            clamp_depth: false,
+        }),
+        // not explained in great detail but has to do with multisampling
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0, // means use all samples
+            // antialiasing setting
+            alpha_to_coverage_enabled: false,
+        },
+    })
+}
@@ -0,0 +1,136 @@
+// getting pixels back off the gpu, used for screenshots and the image tests
+use anyhow::*;
+use std::path::Path;
+
+// copies between textures and buffers want each row to be a multiple of 256 bytes
+// texture.rs doesn't hit this because queue.write_texture doesn't care, but copy_texture_to_buffer does
+pub fn padded_bytes_per_row(unpadded_bytes_per_row: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (unpadded_bytes_per_row + align - 1) / align * align
+}
+
+// copy mip 0 of a 2d texture into a buffer we can map, then take the row padding back out
+// the texture needs to have been made with COPY_SRC
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> Result<image::RgbaImage> {
+    read_texture_level(device, queue, texture, format, 0, width, height)
+}
+
+// same as read_texture for any mip level, width and height are the level's size
+pub fn read_texture_level(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    mip_level: u32,
+    width: u32,
+    height: u32,
+) -> Result<image::RgbaImage> {
+    // the swapchain is usually bgra so we have to know to swap the channels around
+    let swap_red_blue = match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        _ => bail!("can't read back a texture with format {:?}", format),
+    };
+
+    let unpadded_bytes_per_row = 4 * width;
+    let padded_bytes_per_row = padded_bytes_per_row(unpadded_bytes_per_row);
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::TextureCopyView {
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::BufferCopyView {
+            buffer: &output_buffer,
+            layout: wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: padded_bytes_per_row,
+                rows_per_image: height,
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    // mapping only finishes when the device gets polled
+    let slice = output_buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    futures::executor::block_on(mapping)?;
+
+    // drop the padding at the end of each row
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    for row in slice.get_mapped_range().chunks(padded_bytes_per_row as usize) {
+        pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+    }
+    output_buffer.unmap();
+
+    if swap_red_blue {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    image::RgbaImage::from_raw(width, height, pixels).context("readback buffer was the wrong size")
+}
+
+// write the image out, the extension picks the format
+// png and jpeg go through the image crate, exr is written as linear floats
+pub fn save_image<P: AsRef<Path>>(img: &image::RgbaImage, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") | Some("jpg") | Some("jpeg") => img.save(path)?,
+        Some("exr") => {
+            // the pixels we read back are srgb, exr wants linear values
+            exr::prelude::write_rgba_file(
+                path,
+                img.width() as usize,
+                img.height() as usize,
+                |x, y| {
+                    let pixel = img.get_pixel(x as u32, y as u32);
+                    (
+                        srgb_to_linear(pixel[0]),
+                        srgb_to_linear(pixel[1]),
+                        srgb_to_linear(pixel[2]),
+                        pixel[3] as f32 / 255.0,
+                    )
+                },
+            )?
+        }
+        _ => bail!("don't know how to save {}", path.display()),
+    }
+    Ok(())
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,142 @@
+// recording a run of frames to disk for demo videos
+// every frame is one fixed step of simulated time, so a recording comes out the same no matter how slow
+// the readback and writing are
+// a path ending in .y4m gets a raw video stream (ffmpeg and most players read it), anything else is a folder
+// of numbered pngs
+use anyhow::*;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+enum Output {
+    Pngs(PathBuf),
+    // the size gets fixed by the first frame since it goes in the header
+    Y4m {
+        writer: std::io::BufWriter<std::fs::File>,
+        size: Option<(u32, u32)>,
+    },
+}
+
+pub struct Recorder {
+    output: Output,
+    fps: u32,
+    // stop on our own after this many, None keeps going till it gets finished
+    max_frames: Option<u32>,
+    frames_written: u32,
+}
+
+impl Recorder {
+    pub fn new<P: AsRef<Path>>(path: P, fps: u32, max_frames: Option<u32>) -> Result<Self> {
+        let path = path.as_ref();
+        if fps == 0 {
+            bail!("can't record at 0 frames a second");
+        }
+        let is_y4m = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("y4m"));
+        let output = if is_y4m {
+            let file = std::fs::File::create(path).with_context(|| format!("couldn't create {}", path.display()))?;
+            Output::Y4m {
+                writer: std::io::BufWriter::new(file),
+                size: None,
+            }
+        } else {
+            std::fs::create_dir_all(path).with_context(|| format!("couldn't create {}", path.display()))?;
+            Output::Pngs(path.to_path_buf())
+        };
+        Ok(Self {
+            output,
+            fps,
+            max_frames,
+            frames_written: 0,
+        })
+    }
+
+    // how far to move State::update along between frames
+    pub fn frame_time(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.fps as f64)
+    }
+
+    pub fn frames_written(&self) -> u32 {
+        self.frames_written
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.max_frames.map_or(false, |max| self.frames_written >= max)
+    }
+
+    pub fn write_frame(&mut self, frame: &image::RgbaImage) -> Result<()> {
+        match &mut self.output {
+            Output::Pngs(folder) => {
+                // zero padded so the files sort in order and ffmpeg's %06d pattern finds them
+                let path = folder.join(format!("frame_{:06}.png", self.frames_written));
+                frame.save(&path).with_context(|| format!("couldn't save {}", path.display()))?;
+            }
+            Output::Y4m { writer, size } => {
+                let (width, height) = frame.dimensions();
+                match size {
+                    None => {
+                        // 4:2:0 with the usual bt.601 studio range, which is what y4m assumes
+                        writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg", width, height, self.fps)?;
+                        *size = Some((width, height));
+                    }
+                    Some(size) if *size != (width, height) => bail!(
+                        "a y4m recording can't change size, started at {}x{} and got a {}x{} frame",
+                        size.0,
+                        size.1,
+                        width,
+                        height
+                    ),
+                    Some(_) => {}
+                }
+                writer.write_all(b"FRAME\n")?;
+                writer.write_all(&rgba_to_yuv420(frame))?;
+            }
+        }
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    // flush whatever's still buffered, dropping the recorder does too but can't say if it went wrong
+    pub fn finish(self) -> Result<()> {
+        if let Output::Y4m { mut writer, .. } = self.output {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// the y plane at full size followed by u and v at half size (rounded up), each chroma sample
+// is the average of the 2x2 block it covers
+fn rgba_to_yuv420(frame: &image::RgbaImage) -> Vec<u8> {
+    let (width, height) = frame.dimensions();
+    let (chroma_width, chroma_height) = ((width + 1) / 2, (height + 1) / 2);
+    let mut y_plane = Vec::with_capacity((width * height) as usize);
+    for pixel in frame.pixels() {
+        let [r, g, b] = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+        y_plane.push((16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8);
+    }
+    let mut u_plane = Vec::with_capacity((chroma_width * chroma_height) as usize);
+    let mut v_plane = Vec::with_capacity((chroma_width * chroma_height) as usize);
+    for cy in 0..chroma_height {
+        for cx in 0..chroma_width {
+            let (mut r, mut g, mut b, mut count) = (0.0, 0.0, 0.0, 0.0);
+            for y in (cy * 2)..(cy * 2 + 2).min(height) {
+                for x in (cx * 2)..(cx * 2 + 2).min(width) {
+                    let pixel = frame.get_pixel(x, y);
+                    r += pixel[0] as f32;
+                    g += pixel[1] as f32;
+                    b += pixel[2] as f32;
+                    count += 1.0;
+                }
+            }
+            let (r, g, b) = (r / count, g / count, b / count);
+            u_plane.push((128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8);
+            v_plane.push((128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8);
+        }
+    }
+    y_plane.extend(u_plane);
+    y_plane.extend(v_plane);
+    y_plane
+}
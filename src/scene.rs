@@ -0,0 +1,348 @@
+// importing whole glTF 2.0 scenes (.gltf with its buffers/images next to it, or a single .glb)
+// each node's world transform turns into an instance of its mesh, so a scene is drawn with the same
+// pipeline and shaders as everything else
+use crate::instance::InstanceRaw;
+use crate::mesh::{Aabb, DrawMesh};
+use crate::mipmap::MipmapGenerator;
+use crate::model::Material;
+use crate::{texture, Vertex};
+use anyhow::*;
+use cgmath::{Matrix4, SquareMatrix};
+use std::path::Path;
+use wgpu::util::DeviceExt;
+
+pub struct Node {
+    // relative to the parent node
+    pub local_transform: Matrix4<f32>,
+    // indexes into Scene::nodes
+    pub children: Vec<usize>,
+    // index into Scene::meshes
+    pub mesh: Option<usize>,
+}
+
+// a glTF mesh is split into primitives, each with a single material
+pub struct Mesh {
+    // the material of each primitive is an index into Scene::materials
+    pub primitives: Vec<crate::mesh::Mesh>,
+}
+
+pub struct SceneMaterial {
+    // the base color texture (or plain white) ready to bind at set = 0
+    pub material: Material,
+    // multiplied with the texture, goes to the shader as the instance tint
+    pub base_color_factor: [f32; 4],
+}
+
+// one draw call, a primitive along with the transforms of every node that uses its mesh
+struct Draw {
+    mesh: usize,
+    primitive: usize,
+    instance_buffer: wgpu::Buffer,
+    num_instances: u32,
+}
+
+pub struct Scene {
+    pub nodes: Vec<Node>,
+    // the nodes without parents in the scene we loaded
+    pub roots: Vec<usize>,
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<SceneMaterial>,
+    draws: Vec<Draw>,
+}
+
+impl Scene {
+    // gltf::import takes care of .glb files, embedded base64 data and external files
+    pub fn load<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mip_generator: &MipmapGenerator,
+        layout: &wgpu::BindGroupLayout,
+        path: P,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let (document, buffers, images) =
+            gltf::import(path).with_context(|| format!("couldn't import {}", path.display()))?;
+
+        // images can be embedded in the file or sit next to it, import already read them either way
+        let mut materials = Vec::new();
+        for gltf_material in document.materials() {
+            let pbr = gltf_material.pbr_metallic_roughness();
+            let name = gltf_material.name().unwrap_or("gltf material");
+            let material = match pbr.base_color_texture() {
+                Some(info) => {
+                    let source = info.texture().source().index();
+                    let img = image_from_gltf(&images[source])?;
+                    let options = texture_options(&info.texture().sampler(), name);
+                    let diffuse_texture = texture::Texture::from_image_with_options(device, queue, mip_generator, &img, &options)?;
+                    Material::new(device, name, diffuse_texture, layout)
+                }
+                None => Material::from_color(device, queue, mip_generator, name, [1.0; 3], layout)?,
+            };
+            materials.push(SceneMaterial {
+                material,
+                base_color_factor: pbr.base_color_factor(),
+            });
+        }
+        // primitives without a material use the spec's default, plain white
+        let default_material = materials.len();
+        materials.push(SceneMaterial {
+            material: Material::from_color(device, queue, mip_generator, "default material", [1.0; 3], layout)?,
+            base_color_factor: [1.0; 4],
+        });
+
+        let mut meshes = Vec::new();
+        for gltf_mesh in document.meshes() {
+            let mut primitives = Vec::new();
+            for gltf_primitive in gltf_mesh.primitives() {
+                if gltf_primitive.mode() != gltf::mesh::Mode::Triangles {
+                    // our pipeline only knows triangle lists
+                    log::warn!("skipping a {:?} primitive in {:?}", gltf_primitive.mode(), gltf_mesh.name());
+                    continue;
+                }
+                let reader = gltf_primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let positions: Vec<[f32; 3]> = reader
+                    .read_positions()
+                    .context("primitive has no positions")?
+                    .collect();
+                let normals: Vec<[f32; 3]> = reader
+                    .read_normals()
+                    .map(|normals| normals.collect())
+                    .unwrap_or_else(|| vec![[0.0, 0.0, 1.0]; positions.len()]);
+                // the base color texture says which uv set it uses, most files only have the one
+                let tex_coord_set = gltf_primitive
+                    .material()
+                    .pbr_metallic_roughness()
+                    .base_color_texture()
+                    .map_or(0, |info| info.tex_coord());
+                let tex_coords: Vec<[f32; 2]> = reader
+                    .read_tex_coords(tex_coord_set)
+                    .map(|tex_coords| tex_coords.into_f32().collect())
+                    .unwrap_or_else(|| vec![[0.0, 0.0]; positions.len()]);
+                let vertices: Vec<Vertex> = positions
+                    .iter()
+                    .zip(normals.iter())
+                    .zip(tex_coords.iter())
+                    .map(|((position, normal), tex_coords)| Vertex {
+                        position: *position,
+                        tex_coords: *tex_coords,
+                        normal: *normal,
+                    })
+                    .collect();
+                // no indices means every three vertices are a triangle
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..vertices.len() as u32).collect(),
+                };
+
+                let material = gltf_primitive.material().index().unwrap_or(default_material);
+                primitives.push(crate::mesh::Mesh::new(
+                    device,
+                    gltf_mesh.name().unwrap_or("gltf primitive"),
+                    &vertices,
+                    &indices,
+                    material,
+                ));
+            }
+            meshes.push(Mesh { primitives });
+        }
+
+        let nodes: Vec<Node> = document
+            .nodes()
+            .map(|node| Node {
+                local_transform: node.transform().matrix().into(),
+                children: node.children().map(|child| child.index()).collect(),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+            })
+            .collect();
+        // files don't have to say which scene is the default, take the first one then
+        let gltf_scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .context("gltf file has no scenes")?;
+        let roots: Vec<usize> = gltf_scene.nodes().map(|node| node.index()).collect();
+
+        let mut scene = Self {
+            nodes,
+            roots,
+            meshes,
+            materials,
+            draws: Vec::new(),
+        };
+        scene.build_draws(device)?;
+        Ok(scene)
+    }
+
+    // walk down from the roots multiplying transforms as we go
+    // a node showing up twice means a cycle (or a node with two parents), which the spec doesn't allow
+    // and which would otherwise keep this going forever
+    pub fn world_transforms(&self) -> Result<Vec<Option<Matrix4<f32>>>> {
+        let mut world = vec![None; self.nodes.len()];
+        let mut stack: Vec<(usize, Matrix4<f32>)> =
+            self.roots.iter().map(|&root| (root, Matrix4::identity())).collect();
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            if world[index].is_some() {
+                bail!("node {} is reached more than once, the node hierarchy has a cycle", index);
+            }
+            let transform = parent * node.local_transform;
+            world[index] = Some(transform);
+            for &child in &node.children {
+                stack.push((child, transform));
+            }
+        }
+        Ok(world)
+    }
+
+    // a box around everything in the scene, after the node transforms are applied
+    pub fn bounds(&self) -> Result<Option<Aabb>> {
+        use cgmath::Transform;
+        let world = self.world_transforms()?;
+        let mut total: Option<Aabb> = None;
+        for (node, transform) in self.nodes.iter().zip(world.iter()) {
+            let (mesh, transform) = match (node.mesh, transform) {
+                (Some(mesh), Some(transform)) => (mesh, transform),
+                _ => continue,
+            };
+            for primitive in &self.meshes[mesh].primitives {
+                let (min, max) = (primitive.bounds.min, primitive.bounds.max);
+                // transform all eight corners, the box can rotate
+                let corners = (0..8).map(|i| {
+                    let corner = cgmath::Point3::new(
+                        if i & 1 == 0 { min.x } else { max.x },
+                        if i & 2 == 0 { min.y } else { max.y },
+                        if i & 4 == 0 { min.z } else { max.z },
+                    );
+                    transform.transform_point(corner).into()
+                });
+                if let Some(bounds) = Aabb::from_points(corners) {
+                    total = Some(total.map_or(bounds, |total| total.union(&bounds)));
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    // group up all the nodes using each mesh so every primitive is a single instanced draw
+    fn build_draws(&mut self, device: &wgpu::Device) -> Result<()> {
+        let world = self.world_transforms()?;
+        let mut draws = Vec::new();
+        for (mesh_index, mesh) in self.meshes.iter().enumerate() {
+            let transforms: Vec<Matrix4<f32>> = self
+                .nodes
+                .iter()
+                .zip(world.iter())
+                .filter(|(node, _)| node.mesh == Some(mesh_index))
+                .filter_map(|(_, transform)| *transform)
+                .collect();
+            if transforms.is_empty() {
+                continue;
+            }
+            for (primitive_index, primitive) in mesh.primitives.iter().enumerate() {
+                let tint = self.materials[primitive.material].base_color_factor;
+                let instances: Vec<InstanceRaw> = transforms
+                    .iter()
+                    .map(|transform| InstanceRaw::new(*transform, tint))
+                    .collect();
+                let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("gltf Instance Buffer"),
+                    contents: bytemuck::cast_slice(&instances),
+                    usage: wgpu::BufferUsage::VERTEX,
+                });
+                draws.push(Draw {
+                    mesh: mesh_index,
+                    primitive: primitive_index,
+                    instance_buffer,
+                    num_instances: instances.len() as u32,
+                });
+            }
+        }
+        self.draws = draws;
+        Ok(())
+    }
+}
+
+// gltf samplers default to repeat and leave filtering up to us
+fn texture_options<'a>(sampler: &gltf::texture::Sampler, label: &'a str) -> texture::TextureOptions<'a> {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    let mut options = texture::TextureOptions::new(label);
+    options.address_modes = [
+        address_mode(sampler.wrap_s()),
+        address_mode(sampler.wrap_t()),
+        wgpu::AddressMode::ClampToEdge,
+    ];
+    if sampler.mag_filter() == Some(MagFilter::Nearest) {
+        options.mag_filter = wgpu::FilterMode::Nearest;
+    }
+    // the min filter says both how to filter and whether to use mips at all
+    match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::Linear) => {
+            options.mipmaps = texture::MipmapMode::None;
+            if sampler.min_filter() == Some(MinFilter::Nearest) {
+                options.min_filter = wgpu::FilterMode::Nearest;
+            }
+        }
+        Some(MinFilter::NearestMipmapNearest) => {
+            options.min_filter = wgpu::FilterMode::Nearest;
+            options.mipmap_filter = wgpu::FilterMode::Nearest;
+        }
+        Some(MinFilter::LinearMipmapNearest) => options.mipmap_filter = wgpu::FilterMode::Nearest,
+        Some(MinFilter::NearestMipmapLinear) => options.min_filter = wgpu::FilterMode::Nearest,
+        Some(MinFilter::LinearMipmapLinear) | None => {}
+    }
+    options
+}
+
+// the image crate wants typed pixels, gltf hands back bytes plus a format
+fn image_from_gltf(data: &gltf::image::Data) -> Result<image::DynamicImage> {
+    use gltf::image::Format;
+    use image::{DynamicImage, ImageBuffer};
+    let (width, height) = (data.width, data.height);
+    let pixels = data.pixels.clone();
+    // 16 bit images come through as native endian byte pairs
+    let pixels16 = || -> Vec<u16> {
+        data.pixels
+            .chunks_exact(2)
+            .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+            .collect()
+    };
+    match data.format {
+        Format::R8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        Format::R8G8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+        Format::R8G8B8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+        Format::R8G8B8A8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+        Format::B8G8R8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageBgr8),
+        Format::B8G8R8A8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageBgra8),
+        Format::R16 => ImageBuffer::from_raw(width, height, pixels16()).map(DynamicImage::ImageLuma16),
+        Format::R16G16 => ImageBuffer::from_raw(width, height, pixels16()).map(DynamicImage::ImageLumaA16),
+        Format::R16G16B16 => ImageBuffer::from_raw(width, height, pixels16()).map(DynamicImage::ImageRgb16),
+        Format::R16G16B16A16 => {
+            ImageBuffer::from_raw(width, height, pixels16()).map(DynamicImage::ImageRgba16)
+        }
+    }
+    .context("gltf image data doesn't match its size")
+}
+
+// same idea as model::DrawModel, needs the pipeline and camera bind group set already
+pub trait DrawScene<'a> {
+    fn draw_scene(&mut self, scene: &'a Scene);
+}
+
+impl<'a, 'b> DrawScene<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_scene(&mut self, scene: &'b Scene) {
+        for draw in &scene.draws {
+            let primitive = &scene.meshes[draw.mesh].primitives[draw.primitive];
+            let material = &scene.materials[primitive.material];
+            self.set_bind_group(0, &material.material.bind_group, &[]);
+            self.set_vertex_buffer(1, draw.instance_buffer.slice(..));
+            self.draw_mesh_instanced(primitive, 0..draw.num_instances);
+        }
+    }
+}
@@ -1,14 +1,30 @@
 #version 450
 
+// build.rs compiles one of these for every combination, see ShaderFragPermutation in shaders.rs
+// TEXTURED samples t_diffuse, without it the color is just the tint
+// ALPHA_TEST throws away mostly see through pixels, for sprites that get drawn in any order
+#pragma permutation TEXTURED ALPHA_TEST
 
 layout ( location = 0) in vec2 v_tex_coords;
+layout ( location = 1) in vec4 v_tint; // comes from the instance
 layout (location = 0 ) out vec4 f_color;
 
+#ifdef TEXTURED
 // put these together to make the first valuee for the texture function
 layout(set = 0,binding = 0) uniform texture2D t_diffuse; // thihs is our texture vieew
 layout(set = 0, binding = 1) uniform sampler s_diffuse;// thtis is the sampler we created
+#endif
 
 void main () {
+#ifdef TEXTURED
     vec4 res =texture(sampler2D(t_diffuse,s_diffuse),v_tex_coords); 
-    f_color = res;
-}
\ No newline at end of file
+#else
+    vec4 res = vec4(1.0);
+#endif
+    f_color = res * v_tint;
+#ifdef ALPHA_TEST
+    if (f_color.a < 0.5) {
+        discard;
+    }
+#endif
+}
@@ -7,12 +7,33 @@
 //    vec2(0.5,-0.5)
 //);
 
-layout (location = 0) in vec3 a_position;
-layout (location = 1) in vec2 a_tex_coords;
+// which buffer each input comes from, in the order they're laid out in it
+// build.rs turns these into the ShaderVertex and ShaderInstance structs (Vertex and InstanceRaw) and their layouts
+// in shaders.rs, a field per input named after it
+// normal:vec3 is in the vertex buffer for lighting, nothing here reads it yet
+#pragma vertex_buffer vertex 0 1 normal:vec3
+#pragma vertex_buffer instance 5 6 7 8 9 10
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec2 tex_coords;
+
+// per instance data from InstanceRaw, a mat4 has to come in as four vec4 columns
+layout (location = 5) in vec4 model_matrix_0;
+layout (location = 6) in vec4 model_matrix_1;
+layout (location = 7) in vec4 model_matrix_2;
+layout (location = 8) in vec4 model_matrix_3;
+layout (location = 9) in vec4 tint;
+layout (location = 10) in vec4 uv_rect; // xy is the offset, zw the scale, for picking a sprite out of an atlas
 
 layout (location = 0) out vec2 v_tex_coords;
+layout (location = 1) out vec4 v_tint;
+
+// set 1 is the camera, shared with the other world shaders
+#include <camera.glsl>
 
 void main () {
-    v_tex_coords = a_tex_coords;
-    gl_Position = vec4(a_position,1.0);
+    mat4 model_matrix = mat4(model_matrix_0, model_matrix_1, model_matrix_2, model_matrix_3);
+    v_tex_coords = tex_coords * uv_rect.zw + uv_rect.xy;
+    v_tint = tint;
+    gl_Position = u_view_proj * model_matrix * vec4(position,1.0);
 }
\ No newline at end of file
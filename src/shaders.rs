@@ -0,0 +1,22 @@
+// every shader build.rs compiled, embedded in the binary
+// each one is a constant named after its file (shader.vert is SHADER_VERT), shaders with `#pragma permutation`
+// flags also get a struct to pick a permutation with, e.g. ShaderFragPermutation { textured: true, .. }.shader()
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
+
+#[derive(Copy, Clone, Debug)]
+pub struct Shader {
+    // the file name plus any defines, shows up in wgpu's error messages
+    pub name: &'static str,
+    pub spirv: &'static [u8],
+}
+
+impl Shader {
+    // the same thing wgpu::include_spirv! makes, for device.create_shader_module
+    pub fn module(&self) -> wgpu::ShaderModuleDescriptor<'static> {
+        wgpu::ShaderModuleDescriptor {
+            label: Some(self.name),
+            source: wgpu::util::make_spirv(self.spirv),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        }
+    }
+}
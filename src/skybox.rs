@@ -0,0 +1,81 @@
+// a cubemap drawn behind everything else
+// it's a fullscreen triangle on the far plane (skybox.vert), drawn after the scene with a depth test that
+// doesn't write, so it only shows up where nothing else got drawn
+use crate::pipeline::{self, DepthSettings};
+use crate::shaders;
+use crate::texture::Texture;
+
+pub struct Skybox {
+    pub texture: Texture,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Skybox {
+    // texture has to have a Cube view, see cubemap.rs
+    // camera_layout is the same camera bind group layout the main pipeline uses at set = 1
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        camera_layout: &wgpu::BindGroupLayout,
+        texture: Texture,
+    ) -> Self {
+        // set 0 comes from the bindings in skybox.frag, see build.rs
+        let bind_group_layout = device.create_bind_group_layout(&shaders::SKYBOX_SET_0);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("skybox bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("skybox pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout, camera_layout],
+            push_constant_ranges: &[],
+        });
+        let vs_module = device.create_shader_module(&shaders::SKYBOX_VERT.module());
+        let fs_module = device.create_shader_module(&shaders::SKYBOX_FRAG.module());
+        // LessEqual lets it through where the depth buffer is still at the 1.0 it was cleared to
+        let pipeline = pipeline::create_render_pipeline(
+            device,
+            "skybox pipeline",
+            &layout,
+            color_format,
+            Some(DepthSettings::READ_ONLY),
+            &[],
+            &vs_module,
+            &fs_module,
+        );
+        Self {
+            texture,
+            bind_group,
+            pipeline,
+        }
+    }
+}
+
+// draw the sky after everything else in the pass, it changes the pipeline so set it back if more comes after
+pub trait DrawSkybox<'a> {
+    fn draw_skybox(&mut self, skybox: &'a Skybox, camera_bind_group: &'a wgpu::BindGroup);
+}
+
+impl<'a, 'b> DrawSkybox<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_skybox(&mut self, skybox: &'b Skybox, camera_bind_group: &'b wgpu::BindGroup) {
+        self.set_pipeline(&skybox.pipeline);
+        self.set_bind_group(0, &skybox.bind_group, &[]);
+        self.set_bind_group(1, camera_bind_group, &[]);
+        // no vertex buffers, the triangle comes out of gl_VertexIndex
+        self.draw(0..3, 0..1);
+    }
+}
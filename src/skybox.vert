@@ -0,0 +1,17 @@
+#version 450
+
+layout (location = 0) out vec3 v_direction;
+
+// set 1 is the camera, shared with the other world shaders
+#include <camera.glsl>
+
+// a fullscreen triangle sitting on the far plane, so anything drawn before it stays in front
+void main() {
+    vec2 pos = vec2(float((gl_VertexIndex & 1) << 2) - 1.0, float((gl_VertexIndex & 2) << 1) - 1.0);
+    // back out of the projection to find where this pixel looks
+    vec4 unprojected = inverse(u_proj) * vec4(pos, 1.0, 1.0);
+    // only the rotation part of the view, the sky doesn't move when the camera does
+    mat3 inverse_rotation = transpose(mat3(u_view));
+    v_direction = inverse_rotation * (unprojected.xyz / unprojected.w);
+    gl_Position = vec4(pos, 1.0, 1.0);
+}
@@ -0,0 +1,41 @@
+// a texture that gets new pixels from the cpu every frame, for video frames, live plots, paint tools and the like
+// no mipmaps since they'd have to be remade every time
+use crate::texture::{MipmapMode, Texture, TextureData, TextureOptions};
+use anyhow::*;
+
+pub struct StreamingTexture {
+    pub texture: Texture,
+    // the next frame gets built up in here so we're not allocating every frame
+    frame: TextureData,
+}
+
+impl StreamingTexture {
+    // rgba8 srgb unless the options pick something else
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, options: &TextureOptions) -> Result<Self> {
+        let options = options.clone().mipmaps(MipmapMode::None);
+        let texture = Texture::new_empty(device, width, height, &options)?;
+        let bytes_per_pixel = texture.format.describe().block_size as usize;
+        if texture.format.describe().block_dimensions != (1, 1) {
+            bail!("streaming textures can't use compressed formats like {:?}", texture.format);
+        }
+        let frame = TextureData {
+            format: texture.format,
+            width,
+            height,
+            layers: 1,
+            bytes: vec![0; width as usize * height as usize * bytes_per_pixel],
+        };
+        Ok(Self { texture, frame })
+    }
+
+    // the raw bytes of the next frame, in the texture's format, rows top to bottom with no padding
+    // change them and call upload()
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.frame.bytes
+    }
+
+    // send the whole frame over
+    pub fn upload(&self, queue: &wgpu::Queue) -> Result<()> {
+        self.texture.write_data(queue, [0, 0], &self.frame)
+    }
+}
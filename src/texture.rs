@@ -1,88 +1,794 @@
+use crate::mipmap::MipmapGenerator;
 use image::GenericImageView;
 use anyhow::*;
+use std::num::NonZeroU8;
+
+// features textures can use when the adapter has them, State asks for these when making the device
+pub const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::from_bits_truncate(
+    wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER.bits() | wgpu::Features::TEXTURE_COMPRESSION_BC.bits(),
+);
 
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
-    pub sampler: wgpu::Sampler
+    pub sampler: wgpu::Sampler,
+    // remembered so later writes can check they fit and convert to the right format
+    pub format: wgpu::TextureFormat,
+    pub size: wgpu::Extent3d,
 }
 
 impl Texture {
-    pub fn from_bytes(device: &wgpu::Device, queue:&wgpu::Queue, bytes :&[u8],label:&str) -> Result<Self> {
+    // the format our depth buffers use, the pipeline's depth_stencil has to match this
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    // a depth buffer the same size as the swapchain, needs remaking whenever the window resizes
+    pub fn create_depth_texture(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, label: &str) -> Self {
+        let size = wgpu::Extent3d {
+            width: sc_desc.width,
+            height: sc_desc.height,
+            depth: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            // we render depth into it, SAMPLED means we could also read it in a shader later (shadows and such)
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // only used if we ever sample the depth texture, compare makes it a comparison sampler
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+            format: Self::DEPTH_FORMAT,
+            size,
+        }
+    }
+
+    // anything image::load_from_memory understands, plus radiance .hdr files which keep their float pixels
+    // and the containers in container.rs (ktx2, dds, exr)
+    // these get a full mip chain made on the gpu unless the file already has one
+    pub fn from_bytes(device: &wgpu::Device, queue:&wgpu::Queue, mip_generator: &MipmapGenerator, bytes :&[u8],label:&str) -> Result<Self> {
+        Self::from_bytes_with_options(device, queue, mip_generator, bytes, &TextureOptions::new(label))
+    }
+    pub fn from_bytes_with_options(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mip_generator: &MipmapGenerator,
+        bytes: &[u8],
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        // ktx2 and dds go up exactly as stored, mips and all
+        match crate::container::detect(bytes) {
+            Some(crate::container::Container::Ktx2) => {
+                let levels = crate::container::load_ktx2(bytes)?;
+                return Self::from_levels(device, queue, mip_generator, &levels, levels.len() as u32, options);
+            }
+            Some(crate::container::Container::Dds) => {
+                let levels = crate::container::load_dds(bytes)?;
+                return Self::from_levels(device, queue, mip_generator, &levels, levels.len() as u32, options);
+            }
+            Some(crate::container::Container::Exr) => {
+                // half floats rather than Rgba32Float, which can't be filtered without an extra feature
+                let format = options.format.unwrap_or(wgpu::TextureFormat::Rgba16Float);
+                let data = crate::container::load_exr(bytes, format)?;
+                let mip_count = match options.mipmaps {
+                    MipmapMode::None => 1,
+                    MipmapMode::Gpu => crate::mipmap::mip_level_count(data.width, data.height),
+                    MipmapMode::Cpu => bail!("{}: the image crate can't shrink float pixels, use gpu mipmaps", options.label),
+                };
+                return Self::from_levels(device, queue, mip_generator, &[data], mip_count, options);
+            }
+            None => {}
+        }
+        // the image crate would squash an hdr down to 8 bits, so those get decoded by hand
+        if image::guess_format(bytes)? == image::ImageFormat::Hdr {
+            let decoder = image::codecs::hdr::HdrDecoder::new(bytes)?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?;
+            let format = options.format.unwrap_or(wgpu::TextureFormat::Rgba16Float);
+            let data = TextureData::from_hdr_as(metadata.width, metadata.height, &pixels, format)?;
+            // the cpu path only knows DynamicImages, hdrs always go through the gpu
+            let mip_count = match options.mipmaps {
+                MipmapMode::None => 1,
+                MipmapMode::Gpu => crate::mipmap::mip_level_count(data.width, data.height),
+                MipmapMode::Cpu => bail!("{}: the image crate can't shrink float pixels, use gpu mipmaps", options.label),
+            };
+            return Self::from_levels(device, queue, mip_generator, &[data], mip_count, options);
+        }
         // make an image
         // get an image representation
         let img = image::load_from_memory(bytes)?;
 
         // call from image
-        Self::from_image(device, queue, img, label)
+        Self::from_image_with_options(device, queue, mip_generator, &img, options)
     }
-    pub fn from_image(device: &wgpu::Device, queue:&wgpu::Queue, img: &image::DynamicImage,label:&str) -> Result<Self> {
-
-        // convert to vec? more complex actually ImageBuffer<RGBA<u8>, Vec<u8>>
-        let rgba = img.as_rgba8()?;
-        // get the dimensions of the image
-        let dimensions = img.dimensions();
-
-        // making the actual texture
+    // colors are treated as srgb, which is right for anything meant to be looked at (diffuse maps, photos)
+    pub fn from_image(device: &wgpu::Device, queue:&wgpu::Queue, mip_generator: &MipmapGenerator, img: &image::DynamicImage,label:&str) -> Result<Self> {
+        Self::from_image_with_options(device, queue, mip_generator, img, &TextureOptions::new(label))
+    }
+    pub fn from_image_with_options(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mip_generator: &MipmapGenerator,
+        img: &image::DynamicImage,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let format = options
+            .format
+            .unwrap_or_else(|| TextureData::preferred_format(img, options.color_space));
+        let data = TextureData::from_image_as(img, format, options.color_space)?;
+        let mip_count = crate::mipmap::mip_level_count(data.width, data.height);
+        match options.mipmaps {
+            MipmapMode::None => Self::from_levels(device, queue, mip_generator, &[data], 1, options),
+            MipmapMode::Gpu if crate::mipmap::can_generate_on_gpu(data.format) => {
+                Self::from_levels(device, queue, mip_generator, &[data], mip_count, options)
+            }
+            // asked for, or the blit pass can't draw into this format
+            MipmapMode::Gpu | MipmapMode::Cpu => {
+                let levels = cpu_mip_chain(img, options.color_space, data, mip_count)?;
+                Self::from_levels(device, queue, mip_generator, &levels, mip_count, options)
+            }
+        }
+    }
+    // a 2d array texture with one layer per image, they all have to be the same size
+    // shaders pick the layer with the third texture coordinate (sampler2DArray)
+    pub fn from_layers(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[image::DynamicImage],
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let first = images.first().context("a texture array needs at least one image")?;
+        let (width, height) = first.dimensions();
+        if images.iter().any(|img| img.dimensions() != (width, height)) {
+            bail!("{}: every layer of a texture array has to be the same size", options.label);
+        }
+        let format = options
+            .format
+            .unwrap_or_else(|| TextureData::preferred_format(first, options.color_space));
+        let mip_count = match options.mipmaps {
+            MipmapMode::None => 1,
+            // the gpu blit only does single layer textures, so layers always shrink on the cpu
+            MipmapMode::Gpu | MipmapMode::Cpu => crate::mipmap::mip_level_count(width, height),
+        };
+        // each level has every layer one after the other
+        let mut levels = Vec::new();
+        for level in 0..mip_count {
+            let (level_width, level_height) = ((width >> level).max(1), (height >> level).max(1));
+            let mut bytes = Vec::new();
+            for img in images {
+                let resized;
+                let img = if level == 0 {
+                    img
+                } else {
+                    resized = img.resize_exact(level_width, level_height, image::imageops::FilterType::Triangle);
+                    &resized
+                };
+                bytes.extend(TextureData::from_image_as(img, format, options.color_space)?.bytes);
+            }
+            levels.push(TextureData {
+                format,
+                width: level_width,
+                height: level_height,
+                layers: images.len() as u32,
+                bytes,
+            });
+        }
+        Self::upload_levels(device, queue, &levels, mip_count, options)
+    }
+    // a blank texture for drawing into or writing later, options.format picks the format (rgba8 srgb otherwise)
+    pub fn new_empty(device: &wgpu::Device, width: u32, height: u32, options: &TextureOptions) -> Result<Self> {
+        let format = options.format.unwrap_or(wgpu::TextureFormat::Rgba8UnormSrgb);
+        let mip_level_count = match options.mipmaps {
+            MipmapMode::None => 1,
+            MipmapMode::Gpu | MipmapMode::Cpu => crate::mipmap::mip_level_count(width, height),
+        };
         let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
+            width,
+            height,
             depth: 1,
         };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(options.label),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST | options.extra_usage,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Self::create_sampler(device, options, mip_level_count)?;
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            format,
+            size,
+        })
+    }
+    // levels[0] is the full size image, any levels past the ones given get generated on the gpu
+    // more than one layer makes an array texture (with a D2Array view)
+    pub fn from_levels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mip_generator: &MipmapGenerator,
+        levels: &[TextureData],
+        mip_level_count: u32,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let texture = Self::upload_levels(device, queue, levels, mip_level_count, options)?;
+        if levels.len() < mip_level_count as usize {
+            mip_generator.generate(device, queue, &texture.texture, texture.format, mip_level_count);
+        }
+        Ok(texture)
+    }
+    // makes the texture with room for every level but only writes the ones given
+    fn upload_levels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        levels: &[TextureData],
+        mip_level_count: u32,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let base = levels.first().context("no texture levels to upload")?;
+        // compressed formats and such need a feature turned on when the device is made
+        let required = base.format.describe().required_features;
+        if !device.features().contains(required) {
+            bail!(
+                "{} is {:?} which needs {:?}, and this adapter doesn't support it",
+                options.label,
+                base.format,
+                required - device.features()
+            );
+        }
+        let gpu_levels = levels.len() < mip_level_count as usize;
+        if gpu_levels && (base.layers > 1 || !crate::mipmap::can_generate_on_gpu(base.format)) {
+            bail!(
+                "{} can't have its mipmaps made on the gpu ({:?}, {} layers)",
+                options.label,
+                base.format,
+                base.layers
+            );
+        }
+        // making the actual texture
+        let size = wgpu::Extent3d {
+            width: base.width,
+            height: base.height,
+            depth: base.layers,
+        };
+        // sampled means we want to use it in our shaders, like how we defined them as sampler2D
+        // also if we want to copy data into the texture
+        let mut usage = wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST | options.extra_usage;
+        if gpu_levels {
+            // the mipmap blit draws into the smaller levels
+            usage |= wgpu::TextureUsage::RENDER_ATTACHMENT;
+        }
         // diffuse texture
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            // sampled means we want to use it in our shaders, like how we defined them as sampler2D
-            // also if we want to copy data into the texture
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
-            label,
+            format: base.format,
+            usage,
+            label: Some(options.label),
         });
         // use the queue to put data in the texture
         // can't put the data in the texture using the other referenc
+        for (level, data) in levels.iter().enumerate() {
+            queue.write_texture(
+                //
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO, // I guess it treats textures as 3D so you have to say write to the texture at 0 in 3D
+                },
+                // the pixels already match the format
+                &data.bytes,
+                // specify a layout, haha everything is layouts
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    // write_texture doesn't need the 256 byte alignment, only copies between buffers and textures do (see readback.rs)
+                    bytes_per_row: data.bytes_per_row(),
+                    rows_per_image: data.physical_size().height,
+                },
+                // provide the actual size, every layer at once
+                data.physical_size(),
+            );
+        }
+        // make a view
+        // the default would guess D2 for one layer and D2Array for more, but say it so it's obvious
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(if base.layers > 1 {
+                wgpu::TextureViewDimension::D2Array
+            } else {
+                wgpu::TextureViewDimension::D2
+            }),
+            ..Default::default()
+        });
+        let sampler = Self::create_sampler(device, options, mip_level_count)?;
+        Ok(Self {
+            sampler,
+            view,
+            texture,
+            format: base.format,
+            size,
+        })
+    }
+
+    // replace part of the top mip level, origin is the top left corner in pixels
+    // the smaller mip levels aren't touched, so textures that change a lot should use MipmapMode::None
+    pub fn write_region(&self, queue: &wgpu::Queue, origin: [u32; 2], img: &image::DynamicImage) -> Result<()> {
+        let data = TextureData::from_image_as(img, self.format, self.color_space())?;
+        self.write_data(queue, origin, &data)
+    }
+
+    // what images written into this texture are treated as
+    // srgb formats get colors, the float formats are assumed to hold linear values already
+    pub fn color_space(&self) -> ColorSpace {
+        if self.format.describe().srgb {
+            ColorSpace::Srgb
+        } else {
+            ColorSpace::Linear
+        }
+    }
+
+    // same as write_region for pixels that are already in the texture's format
+    pub fn write_data(&self, queue: &wgpu::Queue, origin: [u32; 2], data: &TextureData) -> Result<()> {
+        if data.format != self.format {
+            bail!("can't write {:?} pixels into a {:?} texture", data.format, self.format);
+        }
+        let [x, y] = origin;
+        // compressed formats can only be written a whole block at a time
+        let (block_width, block_height) = self.format.describe().block_dimensions;
+        if x % block_width as u32 != 0 || y % block_height as u32 != 0 {
+            bail!(
+                "{:?} is written in {}x{} blocks, ({}, {}) isn't on a block corner",
+                self.format,
+                block_width,
+                block_height,
+                x,
+                y
+            );
+        }
+        if x + data.width > self.size.width || y + data.height > self.size.height {
+            bail!(
+                "a {}x{} region at ({}, {}) doesn't fit in a {}x{} texture",
+                data.width,
+                data.height,
+                x,
+                y,
+                self.size.width,
+                self.size.height
+            );
+        }
         queue.write_texture(
-            //
             wgpu::TextureCopyView {
-                texture: &texture,
+                texture: &self.texture,
                 mip_level: 0,
-                origin: wgpu::Origin3d::ZERO, // I guess it treats textures as 3D so you have to say write to the texture at 0 in 3D
+                origin: wgpu::Origin3d { x, y, z: 0 },
             },
-            // use the binary rgba data for our texture
-            rgba,
-            // specify a layout, haha everything is layouts
+            &data.bytes,
             wgpu::TextureDataLayout {
                 offset: 0,
-                bytes_per_row: 4 * dimensions.0, // this is 4 times the width of our image, has to be a multiple of 256 apparently
-                rows_per_image: dimensions.1,
+                bytes_per_row: data.bytes_per_row(),
+                rows_per_image: data.physical_size().height,
             },
-            // provide the actual size
-            size,
+            data.physical_size(),
         );
+        Ok(())
+    }
 
-        // make a view
-
-        let view =
-            texture.create_view(&wgpu::TextureViewDescriptor::default());
-        // and a sampler
-        // this is where you say whether it should read around edges or whatnot
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
+    // this is where you say whether it should read around edges or whatnot
+    pub fn create_sampler(device: &wgpu::Device, options: &TextureOptions, mip_level_count: u32) -> Result<wgpu::Sampler> {
+        let [address_mode_u, address_mode_v, address_mode_w] = options.address_modes;
+        if options.address_modes.contains(&wgpu::AddressMode::ClampToBorder)
+            && !device.features().contains(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER)
+        {
+            bail!("{} wants ClampToBorder addressing but the gpu doesn't support it", options.label);
+        }
+        Ok(device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(options.label),
+            address_mode_u,
+            address_mode_v,
+            address_mode_w,
             // these have to do with when a fragment covers multiple pixels
             // or if multiple fragments for single pixel
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mag_filter: options.mag_filter,
+            min_filter: options.min_filter,
+            // no point blending between levels that don't exist
+            mipmap_filter: if mip_level_count > 1 {
+                options.mipmap_filter
+            } else {
+                wgpu::FilterMode::Nearest
+            },
+            anisotropy_clamp: options.anisotropy,
             ..Default::default()
-        });
+        }))
+    }
+}
+
+// whether a texture gets the smaller mip levels
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MipmapMode {
+    // just the full size image
+    None,
+    // drawn on the gpu from the level above, formats the blit can't draw into get shrunk with the image crate instead
+    Gpu,
+    // every level resized from the full image with the image crate, slower but the same on every machine
+    // only for images the image crate can load, not hdr/exr floats
+    Cpu,
+}
+
+// everything about how a texture gets made and sampled
+// start from new() or a preset and chain the rest, like
+// TextureOptions::new("bricks").repeat().anisotropy(8)
+#[derive(Clone, Debug)]
+pub struct TextureOptions<'a> {
+    pub label: &'a str,
+    pub color_space: ColorSpace,
+    // None picks whatever fits the image best (see TextureData::preferred_format)
+    pub format: Option<wgpu::TextureFormat>,
+    pub mipmaps: MipmapMode,
+    // u, v, w
+    pub address_modes: [wgpu::AddressMode; 3],
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    pub anisotropy: Option<NonZeroU8>,
+    // on top of SAMPLED | COPY_DST which every texture gets
+    pub extra_usage: wgpu::TextureUsage,
+}
+
+impl<'a> TextureOptions<'a> {
+    // srgb colors, gpu mipmaps, clamped edges and trilinear filtering, good for diffuse maps
+    pub fn new(label: &'a str) -> Self {
+        Self {
+            label,
+            color_space: ColorSpace::Srgb,
+            format: None,
+            mipmaps: MipmapMode::Gpu,
+            address_modes: [wgpu::AddressMode::ClampToEdge; 3],
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: None,
+            extra_usage: wgpu::TextureUsage::empty(),
+        }
+    }
+
+    // something we draw into and then sample or read back
+    pub fn render_target(label: &'a str, format: wgpu::TextureFormat) -> Self {
+        Self::new(label)
+            .format(format)
+            .mipmaps(MipmapMode::None)
+            .usage(wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC)
+    }
+
+    pub fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    // images get converted into this, see TextureData::from_image_as for which ones work
+    pub fn format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn mipmaps(mut self, mipmaps: MipmapMode) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    pub fn address_mode(mut self, mode: wgpu::AddressMode) -> Self {
+        self.address_modes = [mode; 3];
+        self
+    }
+
+    pub fn repeat(self) -> Self {
+        self.address_mode(wgpu::AddressMode::Repeat)
+    }
+
+    // keeps textures sharp at glancing angles, the clamp has to be 1, 2, 4, 8 or 16
+    pub fn anisotropy(mut self, clamp: u8) -> Self {
+        self.anisotropy = NonZeroU8::new(clamp);
+        self
+    }
+
+    // render attachment, storage, copy src and so on
+    pub fn usage(mut self, usage: wgpu::TextureUsage) -> Self {
+        self.extra_usage |= usage;
+        self
+    }
+}
+
+// every level resized from the full image (not the previous level) so the blurring doesn't stack up
+fn cpu_mip_chain(
+    img: &image::DynamicImage,
+    color_space: ColorSpace,
+    base: TextureData,
+    mip_count: u32,
+) -> Result<Vec<TextureData>> {
+    let format = base.format;
+    let mut levels = vec![base];
+    for level in 1..mip_count {
+        let width = (img.width() >> level).max(1);
+        let height = (img.height() >> level).max(1);
+        let resized = img.resize_exact(width, height, image::imageops::FilterType::Triangle);
+        levels.push(TextureData::from_image_as(&resized, format, color_space)?);
+    }
+    Ok(levels)
+}
+
+// whether the pixels are colors (stored srgb, the gpu converts to linear when sampling)
+// or plain numbers like normals and masks that should come out exactly as stored
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+// pixels converted into a format the gpu can take directly
+// one mip level, with every layer packed one after the other
+pub struct TextureData {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    // 1 for plain 2d textures
+    pub layers: u32,
+    pub bytes: Vec<u8>,
+}
+
+impl TextureData {
+    // the gpu has no 3 channel formats and no srgb versions of the 1 and 2 channel ones, so
+    // - rgb gets an alpha channel added
+    // - gray and gray+alpha stay small as R8/RG8 when linear, and get spread into rgba when they're srgb colors
+    // - 16 bit images go to half floats since there are no 16 bit normalized formats
+    pub fn preferred_format(img: &image::DynamicImage, color_space: ColorSpace) -> wgpu::TextureFormat {
+        use image::DynamicImage::*;
+        let srgb = color_space == ColorSpace::Srgb;
+        match img {
+            ImageLuma8(_) if !srgb => wgpu::TextureFormat::R8Unorm,
+            ImageLumaA8(_) if !srgb => wgpu::TextureFormat::Rg8Unorm,
+            // bgra can go straight up
+            ImageBgra8(_) if srgb => wgpu::TextureFormat::Bgra8UnormSrgb,
+            ImageBgra8(_) => wgpu::TextureFormat::Bgra8Unorm,
+            ImageLuma16(_) | ImageLumaA16(_) | ImageRgb16(_) | ImageRgba16(_) => wgpu::TextureFormat::Rgba16Float,
+            // everything else is 8 bits per channel and ends up rgba
+            _ if srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            _ => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+
+    // convert any image into the given format
+    // for the float formats color_space says whether to decode srgb first since the gpu won't do it for us
+    pub fn from_image_as(
+        img: &image::DynamicImage,
+        format: wgpu::TextureFormat,
+        color_space: ColorSpace,
+    ) -> Result<Self> {
+        use wgpu::TextureFormat::*;
+        let (width, height) = img.dimensions();
+        let bytes = match format {
+            Rgba8Unorm | Rgba8UnormSrgb => img.to_rgba8().into_raw(),
+            Bgra8Unorm | Bgra8UnormSrgb => img.to_bgra8().into_raw(),
+            R8Unorm => img.to_luma8().into_raw(),
+            Rg8Unorm => img.to_luma_alpha8().into_raw(),
+            Rgba16Float | Rgba32Float => {
+                let srgb = color_space == ColorSpace::Srgb;
+                let floats = img.to_rgba16().pixels().flat_map(|pixel| {
+                    let [r, g, b, a] = pixel.0;
+                    let channel = |value: u16| {
+                        let value = value as f32 / 65535.0;
+                        if srgb {
+                            srgb_to_linear(value)
+                        } else {
+                            value
+                        }
+                    };
+                    [channel(r), channel(g), channel(b), a as f32 / 65535.0]
+                });
+                float_bytes(floats, format)
+            }
+            _ => bail!("can't convert images to {:?}", format),
+        };
         Ok(Self {
-            sampler,
-            view,
-            texture
+            format,
+            width,
+            height,
+            layers: 1,
+            bytes,
+        })
+    }
+
+    // hdr pixels are already linear and can go way past 1.0, so they need a float format
+    pub fn from_hdr_as(
+        width: u32,
+        height: u32,
+        pixels: &[image::Rgb<f32>],
+        format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let rgba: Vec<[f32; 4]> = pixels.iter().map(|pixel| [pixel[0], pixel[1], pixel[2], 1.0]).collect();
+        Self::from_rgba_floats(width, height, &rgba, format)
+    }
+
+    // linear float pixels into Rgba16Float or Rgba32Float
+    pub fn from_rgba_floats(
+        width: u32,
+        height: u32,
+        pixels: &[[f32; 4]],
+        format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        if !matches!(format, wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float) {
+            bail!("float images need a float format, not {:?}", format);
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            layers: 1,
+            bytes: float_bytes(pixels.iter().flatten().copied(), format),
         })
     }
+
+    // compressed formats store blocks of pixels (4x4 for bc) so a "row" is a whole row of blocks
+    pub fn bytes_per_row(&self) -> u32 {
+        let info = self.format.describe();
+        let block_width = info.block_dimensions.0 as u32;
+        (self.width + block_width - 1) / block_width * info.block_size as u32
+    }
+
+    // the size rounded up to whole blocks, small mips of compressed textures still take a full block
+    pub fn physical_size(&self) -> wgpu::Extent3d {
+        let (block_width, block_height) = self.format.describe().block_dimensions;
+        let round_up = |size: u32, block: u8| {
+            let block = block as u32;
+            (size + block - 1) / block * block
+        };
+        wgpu::Extent3d {
+            width: round_up(self.width, block_width),
+            height: round_up(self.height, block_height),
+            depth: self.layers,
+        }
+    }
+}
+
+// halves for Rgba16Float, full floats otherwise
+fn float_bytes<I: Iterator<Item = f32>>(floats: I, format: wgpu::TextureFormat) -> Vec<u8> {
+    if format == wgpu::TextureFormat::Rgba16Float {
+        floats
+            .flat_map(|value| half::f16::from_f32(value).to_bits().to_ne_bytes())
+            .collect()
+    } else {
+        floats.flat_map(f32::to_ne_bytes).collect()
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// these need a gpu (or the software adapter) like the golden tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_texture(state: &crate::State) -> Texture {
+        let options = TextureOptions::new("write region test")
+            .format(wgpu::TextureFormat::Rgba8Unorm)
+            .color_space(ColorSpace::Linear)
+            .mipmaps(MipmapMode::None)
+            .usage(wgpu::TextureUsage::COPY_SRC);
+        Texture::new_empty(&state.device, 4, 4, &options).unwrap()
+    }
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> image::DynamicImage {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(width, height, image::Rgba(color)))
+    }
+
+    #[test]
+    fn write_region_only_touches_the_region() {
+        let state = match crate::State::for_test(4, 4) {
+            Some(state) => state,
+            None => return,
+        };
+        let texture = test_texture(&state);
+        // new textures don't start out cleared, so fill the whole thing first
+        texture.write_region(&state.queue, [0, 0], &solid(4, 4, [0, 0, 255, 255])).unwrap();
+        texture.write_region(&state.queue, [1, 2], &solid(2, 2, [255, 0, 0, 255])).unwrap();
+        let pixels =
+            crate::readback::read_texture(&state.device, &state.queue, &texture.texture, texture.format, 4, 4)
+                .unwrap();
+        for (x, y, pixel) in pixels.enumerate_pixels() {
+            let inside = (1..3).contains(&x) && (2..4).contains(&y);
+            let expected = if inside { [255, 0, 0, 255] } else { [0, 0, 255, 255] };
+            assert_eq!(pixel.0, expected, "pixel ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn cpu_mipmaps_match_the_gpu() {
+        let state = match crate::State::for_test(4, 4) {
+            Some(state) => state,
+            None => return,
+        };
+        let size = 16;
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(size, size, |x, y| {
+            image::Rgba([(x * 255 / (size - 1)) as u8, (y * 255 / (size - 1)) as u8, 128, 255])
+        }));
+        let make = |mipmaps| {
+            let options = TextureOptions::new("mipmap test")
+                .format(wgpu::TextureFormat::Rgba8Unorm)
+                .color_space(ColorSpace::Linear)
+                .mipmaps(mipmaps)
+                .usage(wgpu::TextureUsage::COPY_SRC);
+            Texture::from_image_with_options(&state.device, &state.queue, &state.mip_generator, &img, &options).unwrap()
+        };
+        let (cpu, gpu) = (make(MipmapMode::Cpu), make(MipmapMode::Gpu));
+        for level in 1..crate::mipmap::mip_level_count(size, size) {
+            let level_size = size >> level;
+            let read = |texture: &Texture| {
+                crate::readback::read_texture_level(
+                    &state.device,
+                    &state.queue,
+                    &texture.texture,
+                    texture.format,
+                    level,
+                    level_size,
+                    level_size,
+                )
+                .unwrap()
+            };
+            let (cpu_pixels, gpu_pixels) = (read(&cpu), read(&gpu));
+            // the image crate's triangle filter reaches past the 2x2 block the blit averages, and clamps
+            // differently at the edges, so they drift apart a little but should be the same picture
+            for (x, y, cpu_pixel) in cpu_pixels.enumerate_pixels() {
+                let gpu_pixel = gpu_pixels.get_pixel(x, y);
+                let difference = cpu_pixel.0.iter().zip(gpu_pixel.0.iter()).map(|(a, b)| (*a as i16 - *b as i16).abs()).max();
+                assert!(
+                    difference <= Some(16),
+                    "level {} pixel ({}, {}): cpu {:?} gpu {:?}",
+                    level,
+                    x,
+                    y,
+                    cpu_pixel.0,
+                    gpu_pixel.0
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn write_region_rejects_regions_that_dont_fit() {
+        let state = match crate::State::for_test(4, 4) {
+            Some(state) => state,
+            None => return,
+        };
+        let texture = test_texture(&state);
+        assert!(texture.write_region(&state.queue, [3, 3], &solid(2, 2, [255; 4])).is_err());
+        assert!(texture.write_region(&state.queue, [0, 0], &solid(5, 1, [255; 4])).is_err());
+    }
 }